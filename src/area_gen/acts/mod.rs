@@ -0,0 +1,361 @@
+// Acts: graphs of areas chained by their gates, from a town to the last area
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use super::connectors::{Connector, Side};
+use super::maps::define_floor_patterns;
+use super::tiers::AreaModifier;
+use super::{
+    derive_seed, generate_area, AreaGenerationOutput, GenerationConfig, GenerationRequest, Rerolls,
+};
+
+// a random walk can corner itself, the layout is rolled again that many times before
+// settling for the longest walk, the act is then shorter than its config asks
+const LAYOUT_ATTEMPTS: usize = 16;
+
+#[derive(Clone)]
+pub struct ActConfig {
+    // indexes in define_floor_patterns the areas are picked from
+    pub area_maps: Vec<usize>,
+    // map the towns are generated from, without monsters
+    pub town_map: usize,
+    // number of areas between the first town and the end of the act, both inclusive
+    pub length: (usize, usize),
+    // a hub town is added after this many areas, 0 for only the first town
+    pub town_every: usize,
+    // chance for each area of the main path to have a side zone
+    pub side_zone_chance: f64,
+    pub tier: u32,
+}
+
+impl Default for ActConfig {
+    fn default() -> Self {
+        ActConfig {
+            area_maps: vec![0, 1, 2, 3, 4],
+            town_map: 0,
+            length: (6, 9),
+            town_every: 4,
+            side_zone_chance: 0.3,
+            tier: 1,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ActConfigError {
+    // no area at all, or the smaller bound after the bigger one
+    InvalidLength((usize, usize)),
+    NoAreaMaps,
+    // index that is not one of define_floor_patterns
+    UnknownMap(usize),
+    // not a probability
+    InvalidSideZoneChance(f64),
+}
+
+impl ActConfig {
+    pub fn validate(&self) -> Result<(), ActConfigError> {
+        if self.length.0 == 0 || self.length.0 > self.length.1 {
+            return Err(ActConfigError::InvalidLength(self.length));
+        }
+        if self.area_maps.is_empty() {
+            return Err(ActConfigError::NoAreaMaps);
+        }
+        let map_count = define_floor_patterns().len();
+        if let Some(map_index) = self
+            .area_maps
+            .iter()
+            .chain([&self.town_map])
+            .find(|map_index| **map_index >= map_count)
+        {
+            return Err(ActConfigError::UnknownMap(*map_index));
+        }
+        if !(0.0..=1.0).contains(&self.side_zone_chance) {
+            return Err(ActConfigError::InvalidSideZoneChance(self.side_zone_chance));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum NodeKind {
+    Town,
+    Area,
+    // dead end off an area of the main path
+    SideZone,
+}
+
+pub struct ActNode {
+    pub kind: NodeKind,
+    pub map_index: usize,
+    // derived from the act seed and the node index
+    pub seed: u64,
+    pub tier: u32,
+    // cell on the overworld, the areas of an edge are in neighbouring cells
+    pub position: (i32, i32),
+    // gates matching the edges of the node
+    pub entry: Option<Connector>,
+    pub exit: Option<Connector>,
+    pub side_gates: Vec<Connector>,
+}
+
+// From a node to the next one, the connector is the gate on the from node
+pub struct ActEdge {
+    pub from: usize,
+    pub to: usize,
+    pub connector: Connector,
+}
+
+// The main path goes through the nodes in order, side zones come after it
+pub struct Act {
+    pub seed: u64,
+    pub nodes: Vec<ActNode>,
+    pub edges: Vec<ActEdge>,
+}
+
+impl ActNode {
+    pub fn request(&self) -> GenerationRequest {
+        GenerationRequest {
+            map_index: self.map_index,
+            seed: self.seed,
+            tier: self.tier,
+            // towns are the same maps, emptied of their packs
+            modifiers: match self.kind {
+                NodeKind::Town => vec![AreaModifier::NoPacks],
+                NodeKind::Area | NodeKind::SideZone => Vec::new(),
+            },
+            entry: self.entry,
+            exit: self.exit,
+            side_gates: self.side_gates.clone(),
+            rerolls: Rerolls::default(),
+        }
+    }
+}
+
+impl Act {
+    pub fn generate_node(&self, index: usize, config: &GenerationConfig) -> AreaGenerationOutput {
+        generate_area(&self.nodes[index].request(), config)
+    }
+}
+
+// Seed of a node, does not depend on the rest of the act so a node can be generated alone
+pub fn node_seed(act_seed: u64, index: usize) -> u64 {
+    derive_seed(act_seed, index as u64)
+}
+
+pub fn generate_act(seed: u64, config: &ActConfig) -> Result<Act, ActConfigError> {
+    config.validate()?;
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let length = rng.gen_range(config.length.0..=config.length.1);
+
+    // main path, a walk on the overworld that does not cross itself
+    let mut path: Vec<(i32, i32)> = Vec::new();
+    for _ in 0..LAYOUT_ATTEMPTS {
+        let walk = random_walk(&mut rng, length);
+        if walk.len() > path.len() {
+            path = walk;
+        }
+        if path.len() == length {
+            break;
+        }
+    }
+    let mut nodes: Vec<ActNode> = Vec::new();
+    let mut edges = Vec::new();
+    for (index, position) in path.iter().enumerate() {
+        let town = index == 0 || (config.town_every > 0 && index % (config.town_every + 1) == 0);
+        let (kind, map_index) = if town {
+            (NodeKind::Town, config.town_map)
+        } else {
+            (NodeKind::Area, *config.area_maps.choose(&mut rng).unwrap())
+        };
+        nodes.push(ActNode {
+            kind,
+            map_index,
+            seed: node_seed(seed, index),
+            tier: config.tier,
+            position: *position,
+            entry: None,
+            exit: None,
+            side_gates: Vec::new(),
+        });
+        if index > 0 {
+            let from = index - 1;
+            let connector = Connector {
+                side: side_towards(path[from], *position),
+                offset: rng.gen_range(0.2..=0.8),
+            };
+            nodes[from].exit = Some(connector);
+            nodes[index].entry = Some(connector.facing());
+            edges.push(ActEdge {
+                from,
+                to: index,
+                connector,
+            });
+        }
+    }
+
+    // side zones in the free cells next to the areas
+    for parent in 0..path.len() {
+        if nodes[parent].kind != NodeKind::Area || !rng.gen_bool(config.side_zone_chance) {
+            continue;
+        }
+        let free: Vec<Side> = SIDES
+            .into_iter()
+            .filter(|side| {
+                let cell = step(nodes[parent].position, *side);
+                nodes.iter().all(|node| node.position != cell)
+            })
+            .collect();
+        let Some(side) = free.choose(&mut rng).copied() else {
+            continue;
+        };
+        let connector = Connector {
+            side,
+            offset: rng.gen_range(0.2..=0.8),
+        };
+        let index = nodes.len();
+        nodes[parent].side_gates.push(connector);
+        nodes.push(ActNode {
+            kind: NodeKind::SideZone,
+            map_index: *config.area_maps.choose(&mut rng).unwrap(),
+            seed: node_seed(seed, index),
+            tier: config.tier,
+            position: step(nodes[parent].position, side),
+            entry: Some(connector.facing()),
+            exit: None,
+            side_gates: Vec::new(),
+        });
+        edges.push(ActEdge {
+            from: parent,
+            to: index,
+            connector,
+        });
+    }
+
+    Ok(Act { seed, nodes, edges })
+}
+
+const SIDES: [Side; 4] = [Side::North, Side::East, Side::South, Side::West];
+
+// Cells of a self avoiding walk from (0, 0), shorter than asked when it corners itself
+fn random_walk(rng: &mut ChaCha8Rng, length: usize) -> Vec<(i32, i32)> {
+    let mut cells = vec![(0, 0)];
+    while cells.len() < length {
+        let current = *cells.last().unwrap();
+        let free: Vec<(i32, i32)> = SIDES
+            .into_iter()
+            .map(|side| step(current, side))
+            .filter(|cell| !cells.contains(cell))
+            .collect();
+        match free.choose(rng) {
+            Some(cell) => cells.push(*cell),
+            None => break,
+        }
+    }
+    cells
+}
+
+// y grows to the south, like on the area grids
+fn step(cell: (i32, i32), side: Side) -> (i32, i32) {
+    match side {
+        Side::North => (cell.0, cell.1 - 1),
+        Side::East => (cell.0 + 1, cell.1),
+        Side::South => (cell.0, cell.1 + 1),
+        Side::West => (cell.0 - 1, cell.1),
+    }
+}
+
+fn side_towards(from: (i32, i32), to: (i32, i32)) -> Side {
+    *SIDES.iter().find(|side| step(from, **side) == to).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_paths_have_the_asked_length() {
+        let config = ActConfig::default();
+        for seed in 0..200 {
+            let act = generate_act(seed, &config).unwrap();
+            let main_path = act
+                .nodes
+                .iter()
+                .filter(|node| node.kind != NodeKind::SideZone)
+                .count();
+            assert!(
+                (config.length.0..=config.length.1).contains(&main_path),
+                "seed {} : {} nodes",
+                seed,
+                main_path
+            );
+        }
+    }
+
+    #[test]
+    fn towns_have_no_packs_at_any_tier() {
+        let config = GenerationConfig::headless();
+        for tier in [1, 5, 16] {
+            let act = generate_act(
+                7,
+                &ActConfig {
+                    tier,
+                    ..ActConfig::default()
+                },
+            )
+            .unwrap();
+            let town = act
+                .nodes
+                .iter()
+                .position(|node| node.kind == NodeKind::Town)
+                .unwrap();
+            let area = act.generate_node(town, &config);
+            assert!(area.packs.is_empty(), "tier {}", tier);
+            assert!(area.enemies.is_empty(), "tier {}", tier);
+        }
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        let with = |change: fn(&mut ActConfig)| {
+            let mut config = ActConfig::default();
+            change(&mut config);
+            generate_act(1, &config).err()
+        };
+        assert_eq!(
+            with(|config| config.length = (9, 6)),
+            Some(ActConfigError::InvalidLength((9, 6)))
+        );
+        assert_eq!(
+            with(|config| config.length = (0, 0)),
+            Some(ActConfigError::InvalidLength((0, 0)))
+        );
+        assert_eq!(
+            with(|config| config.area_maps.clear()),
+            Some(ActConfigError::NoAreaMaps)
+        );
+        assert_eq!(
+            with(|config| config.area_maps.push(99)),
+            Some(ActConfigError::UnknownMap(99))
+        );
+        assert_eq!(
+            with(|config| config.town_map = 5),
+            Some(ActConfigError::UnknownMap(5))
+        );
+        for chance in [-0.1, 1.5] {
+            let config = ActConfig {
+                side_zone_chance: chance,
+                ..ActConfig::default()
+            };
+            assert_eq!(
+                generate_act(1, &config).err(),
+                Some(ActConfigError::InvalidSideZoneChance(chance))
+            );
+        }
+        assert!(matches!(
+            with(|config| config.side_zone_chance = f64::NAN),
+            Some(ActConfigError::InvalidSideZoneChance(_))
+        ));
+        assert_eq!(with(|config| config.side_zone_chance = 1.0), None);
+    }
+}
@@ -0,0 +1,224 @@
+// Daily and weekly challenges: everyone gets the same area for the same date
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use super::maps::define_floor_patterns;
+use super::tiers::AreaModifier;
+use super::{derive_seed, GenerationRequest, Rerolls};
+
+// Day of the gregorian calendar, in UTC
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Date {
+    pub year: i32,
+    // 1 to 12
+    pub month: u32,
+    // 1 to 31
+    pub day: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Period {
+    Daily,
+    // weeks start on monday
+    Weekly,
+}
+
+#[derive(Clone)]
+pub struct ChallengeRules {
+    // challenges with different ids get different areas on the same day
+    pub challenge_id: String,
+    // kept secret on the server, so the areas can not be known in advance
+    pub salt: u64,
+    pub period: Period,
+    // first day of the challenge, the recently used maps are followed from there
+    pub epoch: Date,
+    // the maps of that many previous periods are not picked again, 0 to allow repeats
+    pub exclude_recent: usize,
+    pub tier: u32,
+    pub modifiers: Vec<AreaModifier>,
+}
+
+impl Default for ChallengeRules {
+    fn default() -> Self {
+        ChallengeRules {
+            challenge_id: String::from("daily"),
+            salt: 0,
+            period: Period::Daily,
+            epoch: Date {
+                year: 2024,
+                month: 1,
+                day: 1,
+            },
+            exclude_recent: 2,
+            tier: 1,
+            modifiers: Vec::new(),
+        }
+    }
+}
+
+impl Date {
+    pub fn today() -> Date {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        Date::from_days((seconds / 86_400) as i64)
+    }
+
+    // YYYY-MM-DD
+    pub fn parse(text: &str) -> Option<Date> {
+        let mut parts = text.trim().splitn(3, '-');
+        let date = Date {
+            year: parts.next()?.parse().ok()?,
+            month: parts.next()?.parse().ok()?,
+            day: parts.next()?.parse().ok()?,
+        };
+        // rejects e.g. February 30th
+        (Date::from_days(date.days()) == date).then_some(date)
+    }
+
+    // Days since 1970-01-01
+    pub fn days(&self) -> i64 {
+        // the year starts in march so the leap day is last
+        let year = self.year as i64 - (self.month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = (self.month as i64 + 9) % 12;
+        let day_of_year = (153 * month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    pub fn from_days(days: i64) -> Date {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+        let month = (if month < 10 { month + 3 } else { month - 9 }) as u32;
+        let year = (year_of_era + era * 400) as i32 + (month <= 2) as i32;
+        Date { year, month, day }
+    }
+}
+
+impl Period {
+    // Number of the day or week since 1970-01-01
+    pub fn index(&self, date: Date) -> i64 {
+        match self {
+            Period::Daily => date.days(),
+            // 1970-01-01 was a thursday
+            Period::Weekly => (date.days() + 3).div_euclid(7),
+        }
+    }
+}
+
+// Request of the challenge on that date, the same for everyone with the same rules
+pub fn challenge_request(date: Date, rules: &ChallengeRules) -> GenerationRequest {
+    let last = rules.period.index(date);
+    // each map depends on the maps before it, so they are all replayed from the epoch,
+    // a few thousand picks after ten years of daily challenges, dates before it are picked alone
+    let epoch = rules.period.index(rules.epoch);
+    let first = epoch.min(last);
+    let map_index = *replay_maps(rules, first, last).last().unwrap();
+
+    GenerationRequest {
+        map_index,
+        seed: derive_seed(period_seed(rules, last), 1),
+        tier: rules.tier,
+        modifiers: rules.modifiers.clone(),
+        entry: None,
+        exit: None,
+        side_gates: Vec::new(),
+        rerolls: Rerolls::default(),
+    }
+}
+
+// Map of each period from first to last, none of them is one of the maps of the previous
+// exclude_recent periods
+fn replay_maps(rules: &ChallengeRules, first: i64, last: i64) -> Vec<usize> {
+    let roster = define_floor_patterns().len();
+    // at least one map is left to pick from
+    let exclude = rules.exclude_recent.min(roster - 1);
+    let mut maps: Vec<usize> = Vec::new();
+    for period in first..=last {
+        let seed = period_seed(rules, period);
+        let mut rng = ChaCha8Rng::seed_from_u64(derive_seed(seed, 0));
+        let excluded = &maps[maps.len().saturating_sub(exclude)..];
+        let candidates: Vec<usize> = (0..roster)
+            .filter(|index| !excluded.contains(index))
+            .collect();
+        maps.push(*candidates.choose(&mut rng).unwrap());
+    }
+    maps
+}
+
+fn period_seed(rules: &ChallengeRules, period: i64) -> u64 {
+    // FNV-1a of the id, the std hasher is not stable between releases
+    let mut id_hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in rules.challenge_id.bytes() {
+        id_hash = (id_hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+    }
+    derive_seed(id_hash ^ rules.salt, period as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_maps_are_never_repeated() {
+        for exclude_recent in 0..5 {
+            for salt in 0..10 {
+                let rules = ChallengeRules {
+                    salt,
+                    exclude_recent,
+                    ..ChallengeRules::default()
+                };
+                let epoch = rules.epoch.days();
+                let maps = replay_maps(&rules, epoch, epoch + 3 * 365);
+                let exclude = exclude_recent.min(define_floor_patterns().len() - 1);
+                for (day, map_index) in maps.iter().enumerate() {
+                    let recent = &maps[day.saturating_sub(exclude)..day];
+                    assert!(!recent.contains(map_index), "day {} of {:?}", day, maps);
+                }
+                // the map of the day is the one of the full replay, years later too
+                for day in (0..maps.len()).step_by(29).chain([364, 365, 366, 730]) {
+                    let request = challenge_request(Date::from_days(epoch + day as i64), &rules);
+                    assert_eq!(request.map_index, maps[day], "day {}", day);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn weekly_challenges_change_on_mondays() {
+        let rules = ChallengeRules {
+            period: Period::Weekly,
+            ..ChallengeRules::default()
+        };
+        // 2024-01-01 was a monday
+        let monday = rules.epoch.days();
+        let week = challenge_request(Date::from_days(monday), &rules);
+        for day in 1..7 {
+            let other = challenge_request(Date::from_days(monday + day), &rules);
+            assert_eq!(other.seed, week.seed);
+            assert_eq!(other.map_index, week.map_index);
+        }
+        let next = challenge_request(Date::from_days(monday + 7), &rules);
+        assert_ne!(next.seed, week.seed);
+    }
+
+    #[test]
+    fn dates_round_trip() {
+        let date = Date::parse("2024-02-29").unwrap();
+        assert_eq!(Date::from_days(date.days()), date);
+        assert_eq!(Date::parse("2023-02-29"), None);
+        assert_eq!(Date::from_days(0), Date::parse("1970-01-01").unwrap());
+    }
+}
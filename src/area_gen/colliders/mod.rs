@@ -0,0 +1,99 @@
+// Convex colliders for the physics engine, covering the oob tiles next to the floor
+use super::Grid;
+use crate::area_gen::navmesh::merge_rectangles;
+
+#[derive(Clone, Copy)]
+pub enum ColliderDecomposition {
+    None,
+    // boxes covering every oob tile within band_width tiles of the floor, diagonals included
+    BorderBoxes { band_width: usize },
+}
+
+// Axis aligned box in pixel space
+pub struct BoxCollider {
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+}
+
+pub fn build_colliders(
+    grid: &Grid,
+    decomposition: ColliderDecomposition,
+    tile_size: i32,
+) -> Vec<BoxCollider> {
+    let band_width = match decomposition {
+        ColliderDecomposition::None => return Vec::new(),
+        ColliderDecomposition::BorderBoxes { band_width } => band_width.max(1) as i32,
+    };
+    let width = grid.len() as i32;
+    let height = grid[0].len() as i32;
+    let mut band = vec![vec![false; height as usize]; width as usize];
+    for x in 0..width {
+        for y in 0..height {
+            if !grid[x as usize][y as usize].walkable {
+                continue;
+            }
+            for bx in (x - band_width).max(0)..=(x + band_width).min(width - 1) {
+                for by in (y - band_width).max(0)..=(y + band_width).min(height - 1) {
+                    if !grid[bx as usize][by as usize].walkable {
+                        band[bx as usize][by as usize] = true;
+                    }
+                }
+            }
+        }
+    }
+    merge_rectangles(&band)
+        .iter()
+        .map(|rect| {
+            let (min, max) = rect.px_bounds(tile_size);
+            BoxCollider { min, max }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::maps::define_floor_patterns;
+    use crate::area_gen::{generate_area, tile_center, GenerationConfig, GenerationRequest};
+
+    #[test]
+    fn boxes_cover_the_oob_tiles_next_to_the_floor_only() {
+        for band_width in [1, 2] {
+            let config = GenerationConfig {
+                colliders: ColliderDecomposition::BorderBoxes { band_width },
+                ..GenerationConfig::headless()
+            };
+            for map_index in 0..define_floor_patterns().len() {
+                let area = generate_area(&GenerationRequest::new(map_index, 1), &config);
+                let (width, height) = (area.width as usize, area.height as usize);
+                let covered = |tile: (usize, usize)| {
+                    let point = tile_center(tile, area.tile_size);
+                    area.colliders.iter().any(|collider| {
+                        collider.min.0 < point.0
+                            && point.0 < collider.max.0
+                            && collider.min.1 < point.1
+                            && point.1 < collider.max.1
+                    })
+                };
+                for x in 0..width {
+                    for y in 0..height {
+                        if area.walkable.get(x, y) {
+                            assert!(!covered((x, y)), "walkable {:?} is covered", (x, y));
+                            continue;
+                        }
+                        let next_to_floor = (x.saturating_sub(band_width)..=x + band_width)
+                            .filter(|nx| *nx < width)
+                            .any(|nx| {
+                                (y.saturating_sub(band_width)..=y + band_width)
+                                    .filter(|ny| *ny < height)
+                                    .any(|ny| area.walkable.get(nx, ny))
+                            });
+                        if next_to_floor {
+                            assert!(covered((x, y)), "oob {:?} is not covered", (x, y));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
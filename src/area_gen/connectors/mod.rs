@@ -0,0 +1,283 @@
+// Entry and exit gates on the edges of the area, to chain areas side by side
+use super::maps::TileType;
+use super::{nearest_walkable_tile, Grid};
+
+// gates are this many tiles wide, the same for every map so any two gates match
+pub const GATE_WIDTH: usize = 5;
+// gates stay this many tiles away from the corners of the area
+const CORNER_MARGIN: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Side {
+    // y = 0
+    North,
+    // x = width - 1
+    East,
+    // y = height - 1
+    South,
+    // x = 0
+    West,
+}
+
+// A gate required on one edge of the area
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Connector {
+    pub side: Side,
+    // position of the gate along the edge, 0.0 at the west or north end, 1.0 at the other
+    pub offset: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GateRole {
+    // the player spawns next to it
+    Entry,
+    // holds the exit of the area
+    Exit,
+    // leads to a side zone
+    Side,
+}
+
+// The outermost tiles of the area stay out of bounds, the gate corridor stops one tile before
+// the edge. Two areas join seamlessly when the seam of a gate of one is put on the seam of a gate
+// of the other: the out of bounds tiles of each area then lie under the corridor of the other.
+pub struct Gate {
+    pub role: GateRole,
+    pub side: Side,
+    // first and last corridor tiles along the edge, one tile away from it
+    pub tiles: ((u32, u32), (u32, u32)),
+    // ends of the line between the corridor and the outermost tiles, in pixels
+    pub seam: ((f32, f32), (f32, f32)),
+}
+
+impl Side {
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::North => Side::South,
+            Side::East => Side::West,
+            Side::South => Side::North,
+            Side::West => Side::East,
+        }
+    }
+}
+
+impl Connector {
+    // The connector of the next area that lines up with this one
+    pub fn facing(&self) -> Connector {
+        Connector {
+            side: self.side.opposite(),
+            offset: self.offset,
+        }
+    }
+}
+
+// Gate of a connector on an area of that size, only depends on the size so decoding
+// an area gives back the same gates
+pub fn gate(
+    connector: Connector,
+    role: GateRole,
+    width: usize,
+    height: usize,
+    tile_size: i32,
+) -> Gate {
+    let first = first_along(connector, width, height);
+    let last = first + GATE_WIDTH - 1;
+    let start = tile_at(connector.side, first, 1, width, height);
+    let end = tile_at(connector.side, last, 1, width, height);
+    let tile_size = tile_size as f32;
+    // tile x covers the pixels from (x - 1) * tile_size to x * tile_size
+    let along = ((first as f32 - 1.0) * tile_size, last as f32 * tile_size);
+    let seam = match connector.side {
+        Side::North => ((along.0, 0.0), (along.1, 0.0)),
+        Side::South => {
+            let y = (height as f32 - 2.0) * tile_size;
+            ((along.0, y), (along.1, y))
+        }
+        Side::West => ((0.0, along.0), (0.0, along.1)),
+        Side::East => {
+            let x = (width as f32 - 2.0) * tile_size;
+            ((x, along.0), (x, along.1))
+        }
+    };
+    Gate {
+        role,
+        side: connector.side,
+        tiles: (
+            (start.0 as u32, start.1 as u32),
+            (end.0 as u32, end.1 as u32),
+        ),
+        seam,
+    }
+}
+
+// Gates of the entry, exit and side zones of an area, in that order
+pub fn gates(
+    entry: Option<Connector>,
+    exit: Option<Connector>,
+    side_gates: &[Connector],
+    width: usize,
+    height: usize,
+    tile_size: i32,
+) -> Vec<Gate> {
+    let entry = entry.map(|entry| gate(entry, GateRole::Entry, width, height, tile_size));
+    let exit = exit.map(|exit| gate(exit, GateRole::Exit, width, height, tile_size));
+    let side_gates = side_gates
+        .iter()
+        .map(|side_gate| gate(*side_gate, GateRole::Side, width, height, tile_size));
+    entry.into_iter().chain(exit).chain(side_gates).collect()
+}
+
+// Opens a corridor from the gate to the closest walkable tile, returns the corridor tile
+// in the middle of the gate, a few tiles inside the area
+pub(crate) fn carve_gate(grid: &mut Grid, connector: Connector) -> (usize, usize) {
+    let (width, height) = (grid.len(), grid[0].len());
+    let first = first_along(connector, width, height);
+    let middle = tile_at(connector.side, first + GATE_WIDTH / 2, 1, width, height);
+    let target = nearest_walkable_tile(grid, (middle.0 as i32, middle.1 as i32));
+    let (target_along, target_depth) = along_depth(
+        connector.side,
+        (target.0 as usize, target.1 as usize),
+        width,
+        height,
+    );
+
+    // straight in from the edge, then sideways to the walkable tile
+    let depth = target_depth.max(GATE_WIDTH);
+    for along in first..first + GATE_WIDTH {
+        for d in 1..=depth {
+            open(grid, tile_at(connector.side, along, d, width, height));
+        }
+    }
+    let along_range = first.min(target_along)..=(first + GATE_WIDTH - 1).max(target_along);
+    for along in along_range {
+        for d in depth + 1 - GATE_WIDTH..=depth {
+            open(grid, tile_at(connector.side, along, d, width, height));
+        }
+    }
+
+    tile_at(
+        connector.side,
+        first + GATE_WIDTH / 2,
+        GATE_WIDTH / 2 + 1,
+        width,
+        height,
+    )
+}
+
+fn open(grid: &mut Grid, tile: (usize, usize)) {
+    let tile = &mut grid[tile.0][tile.1];
+    if !tile.walkable {
+        tile.tile_type = TileType::Floor;
+        tile.walkable = true;
+        tile.spawnable = true;
+    }
+}
+
+fn edge_length(side: Side, width: usize, height: usize) -> usize {
+    match side {
+        Side::North | Side::South => width,
+        Side::East | Side::West => height,
+    }
+}
+
+// first gate tile along the edge
+fn first_along(connector: Connector, width: usize, height: usize) -> usize {
+    let length = edge_length(connector.side, width, height);
+    let min = CORNER_MARGIN
+        .min(length.saturating_sub(GATE_WIDTH + 1) / 2)
+        .max(1);
+    let max = length.saturating_sub(min + GATE_WIDTH).max(min);
+    min + ((max - min) as f32 * connector.offset.clamp(0.0, 1.0)).round() as usize
+}
+
+// grid tile at a position along the edge and a depth from it
+fn tile_at(side: Side, along: usize, depth: usize, width: usize, height: usize) -> (usize, usize) {
+    match side {
+        Side::North => (along, depth),
+        Side::South => (along, height - 1 - depth),
+        Side::West => (depth, along),
+        Side::East => (width - 1 - depth, along),
+    }
+}
+
+fn along_depth(side: Side, tile: (usize, usize), width: usize, height: usize) -> (usize, usize) {
+    match side {
+        Side::North => (tile.0, tile.1),
+        Side::South => (tile.0, height - 1 - tile.1),
+        Side::West => (tile.1, tile.0),
+        Side::East => (tile.1, width - 1 - tile.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::pathfinding::{distance_map, PathOptions};
+    use crate::area_gen::test_helpers::grid_of;
+    use crate::area_gen::GenerationRequest;
+    use crate::area_gen::{generate_area, px_to_tile, AreaGenerationOutput, GenerationConfig};
+
+    fn seam_length(gate: &Gate) -> f32 {
+        let ((x0, y0), (x1, y1)) = gate.seam;
+        (x1 - x0).abs() + (y1 - y0).abs()
+    }
+
+    // gate tiles walkable and reachable from the spawn
+    fn assert_open(area: &AreaGenerationOutput, gate: &Gate) {
+        let grid = grid_of(area);
+        let spawn = px_to_tile(
+            (
+                area.player_spawn_position.0 as f32,
+                area.player_spawn_position.1 as f32,
+            ),
+            area.tile_size,
+        );
+        let distances = distance_map(
+            &(&grid, area.tile_size),
+            (spawn.0 as usize, spawn.1 as usize),
+            &PathOptions::default(),
+        );
+        let ((x0, y0), (x1, y1)) = gate.tiles;
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                assert!(area.walkable.get(x as usize, y as usize), "{:?}", (x, y));
+                let distance = distances[x as usize * area.height as usize + y as usize];
+                assert!(distance.is_finite(), "{:?} not reachable", (x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn both_sides_of_a_connector_line_up() {
+        let config = GenerationConfig::headless();
+        for side in [Side::North, Side::East, Side::South, Side::West] {
+            for (offset, maps) in [(0.2, (0, 1)), (0.5, (2, 3)), (0.9, (4, 0))] {
+                let connector = Connector { side, offset };
+                let from = generate_area(
+                    &GenerationRequest {
+                        exit: Some(connector),
+                        ..GenerationRequest::new(maps.0, 1)
+                    },
+                    &config,
+                );
+                let to = generate_area(
+                    &GenerationRequest {
+                        entry: Some(connector.facing()),
+                        ..GenerationRequest::new(maps.1, 2)
+                    },
+                    &config,
+                );
+                let exit = from.gates.iter().find(|gate| gate.role == GateRole::Exit);
+                let entry = to.gates.iter().find(|gate| gate.role == GateRole::Entry);
+                let (exit, entry) = (exit.unwrap(), entry.unwrap());
+                assert_eq!(entry.side, exit.side.opposite());
+                assert_eq!(seam_length(exit), seam_length(entry));
+                assert_eq!(
+                    seam_length(exit),
+                    (GATE_WIDTH * from.tile_size as usize) as f32
+                );
+                assert_open(&from, exit);
+                assert_open(&to, entry);
+            }
+        }
+    }
+}
@@ -0,0 +1,436 @@
+// Contour extraction with marching squares, an alternative to the tile staircase polygons
+use std::collections::HashMap;
+
+use super::{Grid, Shape};
+use crate::area_gen::polygons::{
+    distance_to_segment, polygons_intersect, self_intersects, signed_area,
+};
+
+#[derive(Clone, Copy)]
+pub enum ContourSmoothing {
+    None,
+    // corner cutting, each iteration doubles the number of points
+    Chaikin(u32),
+    // Catmull-Rom spline through the contour, with the number of points per segment
+    CatmullRom(u32),
+}
+
+#[derive(Clone, Copy)]
+pub enum ContourExtraction {
+    // polygons following the tile edges, see find_oob_polygons
+    TileStaircase,
+    // tolerance is the max distance in pixels between a point and the tile boundary
+    MarchingSquares {
+        smoothing: ContourSmoothing,
+        tolerance: f32,
+    },
+}
+
+// Points are stored in half tile units, tile centers are on even coordinates
+type HalfPoint = (i32, i32);
+
+pub fn extract_contours(
+    grid: &Grid,
+    smoothing: ContourSmoothing,
+    tolerance: f32,
+    tile_size: i32,
+) -> Vec<Shape> {
+    let mut loops: Vec<(Vec<(f64, f64)>, f64)> = trace_loops(grid)
+        .into_iter()
+        .map(|half_points| {
+            let points: Vec<(f64, f64)> = half_points
+                .iter()
+                .map(|p| half_to_px(*p, tile_size))
+                .collect();
+            let area = signed_area(&points);
+            (points, area)
+        })
+        .collect();
+    // outer shape first, like find_oob_polygons
+    loops.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let (loops, areas): (Vec<Vec<(f64, f64)>>, Vec<f64>) = loops.into_iter().unzip();
+
+    let mut contours: Vec<Vec<(f64, f64)>> = loops
+        .iter()
+        .map(|points| {
+            let smoothed = match smoothing {
+                ContourSmoothing::None => points.clone(),
+                ContourSmoothing::Chaikin(iterations) => chaikin(points.clone(), iterations),
+                ContourSmoothing::CatmullRom(samples) => catmull_rom(points, samples),
+            };
+            smoothed
+                .into_iter()
+                .map(|p| clamp_to_boundary(grid, p, tolerance as f64, tile_size))
+                .collect()
+        })
+        .collect();
+    revert_folded(
+        &mut contours,
+        &loops,
+        !matches!(smoothing, ContourSmoothing::None),
+    );
+
+    contours
+        .into_iter()
+        .zip(&areas)
+        .map(|(points, area)| {
+            // the hierarchy is rebuilt once all shapes are known
+            Shape {
+                points: points
+                    .into_iter()
+                    .map(|p| (p.0 as f32, p.1 as f32))
+                    .collect(),
+                // the walkable area is always on the left of the contour,
+                // so a positive area means the shape contains floor
+                inner_if_true: *area < 0.0,
+                parent: None,
+                children: Vec::new(),
+                depth: 0,
+            }
+        })
+        .collect()
+}
+
+// Clamping each point on its own can fold a smoothed contour over itself or over
+// its neighbours, those contours are replaced by the unsmoothed ones, on the tile boundary
+fn revert_folded(contours: &mut [Vec<(f64, f64)>], unsmoothed: &[Vec<(f64, f64)>], smoothed: bool) {
+    let mut smoothed = vec![smoothed; contours.len()];
+    for index in 0..contours.len() {
+        if self_intersects(&contours[index]) {
+            contours[index] = unsmoothed[index].clone();
+            smoothed[index] = false;
+        }
+    }
+    let mut reverted = true;
+    while reverted {
+        reverted = false;
+        for first in 0..contours.len() {
+            for second in (first + 1)..contours.len() {
+                if (smoothed[first] || smoothed[second])
+                    && polygons_intersect(&contours[first], &contours[second])
+                {
+                    for index in [first, second] {
+                        contours[index] = unsmoothed[index].clone();
+                        smoothed[index] = false;
+                    }
+                    reverted = true;
+                }
+            }
+        }
+    }
+}
+
+// Marching squares on the tile centers, segments are oriented with the floor on their left
+fn trace_loops(grid: &Grid) -> Vec<Vec<HalfPoint>> {
+    let mut next_point: HashMap<HalfPoint, HalfPoint> = HashMap::new();
+    for x in 0..grid.len() - 1 {
+        for y in 0..grid[0].len() - 1 {
+            let top_left = grid[x][y].walkable;
+            let top_right = grid[x + 1][y].walkable;
+            let bottom_right = grid[x + 1][y + 1].walkable;
+            let bottom_left = grid[x][y + 1].walkable;
+            let (x, y) = (x as i32, y as i32);
+            // middle of each side of the cell
+            let top = (2 * x + 1, 2 * y);
+            let right = (2 * x + 2, 2 * y + 1);
+            let bottom = (2 * x + 1, 2 * y + 2);
+            let left = (2 * x, 2 * y + 1);
+            let center = (2 * x + 1, 2 * y + 1);
+
+            let mut crossed = Vec::new();
+            if top_left != top_right {
+                crossed.push(top);
+            }
+            if top_right != bottom_right {
+                crossed.push(right);
+            }
+            if bottom_right != bottom_left {
+                crossed.push(bottom);
+            }
+            if bottom_left != top_left {
+                crossed.push(left);
+            }
+            let segments = match crossed.len() {
+                2 => {
+                    // any floor corner is on the left of the segment
+                    let corners = [
+                        (top_left, (2 * x, 2 * y)),
+                        (top_right, (2 * x + 2, 2 * y)),
+                        (bottom_right, (2 * x + 2, 2 * y + 2)),
+                        (bottom_left, (2 * x, 2 * y + 2)),
+                    ];
+                    let floor = corners.iter().find(|corner| corner.0).unwrap().1;
+                    vec![(crossed[0], crossed[1], floor)]
+                }
+                // saddle, the floor corners are considered connected through the center
+                4 if top_left => vec![(top, right, center), (bottom, left, center)],
+                4 => vec![(left, top, center), (right, bottom, center)],
+                _ => Vec::new(),
+            };
+            for (a, b, floor) in segments {
+                let turn = (b.0 - a.0) * (floor.1 - a.1) - (b.1 - a.1) * (floor.0 - a.0);
+                if turn > 0 {
+                    next_point.insert(a, b);
+                } else {
+                    next_point.insert(b, a);
+                }
+            }
+        }
+    }
+
+    let mut loops = Vec::new();
+    while let Some(start) = next_point.keys().next().copied() {
+        let mut contour = vec![start];
+        let mut current = next_point.remove(&start).unwrap();
+        while current != start {
+            contour.push(current);
+            current = match next_point.remove(&current) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        loops.push(merge_straight_points(contour));
+    }
+    loops
+}
+
+// Remove points in the middle of horizontal, vertical or diagonal runs
+fn merge_straight_points(contour: Vec<HalfPoint>) -> Vec<HalfPoint> {
+    let n = contour.len();
+    (0..n)
+        .filter(|&k| {
+            let previous = contour[(k + n - 1) % n];
+            let next = contour[(k + 1) % n];
+            let current = contour[k];
+            (current.0 - previous.0) * (next.1 - current.1)
+                != (current.1 - previous.1) * (next.0 - current.0)
+        })
+        .map(|k| contour[k])
+        .collect()
+}
+
+fn half_to_px(point: HalfPoint, tile_size: i32) -> (f64, f64) {
+    let tile_size = tile_size as f64;
+    (
+        point.0 as f64 * tile_size / 2.0 - tile_size / 2.0,
+        point.1 as f64 * tile_size / 2.0 - tile_size / 2.0,
+    )
+}
+
+fn chaikin(mut points: Vec<(f64, f64)>, iterations: u32) -> Vec<(f64, f64)> {
+    for _ in 0..iterations {
+        let n = points.len();
+        let mut smoothed = Vec::with_capacity(n * 2);
+        for k in 0..n {
+            let (a, b) = (points[k], points[(k + 1) % n]);
+            smoothed.push((0.75 * a.0 + 0.25 * b.0, 0.75 * a.1 + 0.25 * b.1));
+            smoothed.push((0.25 * a.0 + 0.75 * b.0, 0.25 * a.1 + 0.75 * b.1));
+        }
+        points = smoothed;
+    }
+    points
+}
+
+// Centripetal parameterization, it does not overshoot into loops on sharp turns
+fn catmull_rom(points: &[(f64, f64)], samples: u32) -> Vec<(f64, f64)> {
+    let n = points.len();
+    let samples = samples.max(1);
+    let mut smoothed = Vec::with_capacity(n * samples as usize);
+    let knot =
+        |a: (f64, f64), b: (f64, f64)| ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).powf(0.25);
+    let lerp = |a: (f64, f64), b: (f64, f64), ta: f64, tb: f64, t: f64| {
+        let w = (t - ta) / (tb - ta);
+        (a.0 + (b.0 - a.0) * w, a.1 + (b.1 - a.1) * w)
+    };
+    for k in 0..n {
+        let p0 = points[(k + n - 1) % n];
+        let p1 = points[k];
+        let p2 = points[(k + 1) % n];
+        let p3 = points[(k + 2) % n];
+        let t0 = 0.0;
+        let t1 = t0 + knot(p0, p1);
+        let t2 = t1 + knot(p1, p2);
+        let t3 = t2 + knot(p2, p3);
+        for sample in 0..samples {
+            let t = t1 + (t2 - t1) * sample as f64 / samples as f64;
+            let a1 = lerp(p0, p1, t0, t1, t);
+            let a2 = lerp(p1, p2, t1, t2, t);
+            let a3 = lerp(p2, p3, t2, t3, t);
+            let b1 = lerp(a1, a2, t0, t2, t);
+            let b2 = lerp(a2, a3, t1, t3, t);
+            smoothed.push(lerp(b1, b2, t1, t2, t));
+        }
+    }
+    smoothed
+}
+
+// Pull a point back toward the closest tile edge between floor and oob if it is too far
+fn clamp_to_boundary(grid: &Grid, point: (f64, f64), tolerance: f64, tile_size: i32) -> (f64, f64) {
+    let tile_size = tile_size as f64;
+    // tile x covers the pixels from (x - 1) * tile_size to x * tile_size
+    let tile = (
+        (point.0 / tile_size).floor() as i32 + 1,
+        (point.1 / tile_size).floor() as i32 + 1,
+    );
+    let radius = (tolerance / tile_size).ceil() as i32 + 2;
+    let mut closest: Option<((f64, f64), f64)> = None;
+    for x in (tile.0 - radius).max(0)..=(tile.0 + radius).min(grid.len() as i32 - 2) {
+        for y in (tile.1 - radius).max(0)..=(tile.1 + radius).min(grid[0].len() as i32 - 2) {
+            let (tx, ty) = (x as usize, y as usize);
+            let right = x as f64 * tile_size;
+            let bottom = y as f64 * tile_size;
+            let mut edges = Vec::new();
+            if grid[tx][ty].walkable != grid[tx + 1][ty].walkable {
+                edges.push(((right, bottom - tile_size), (right, bottom)));
+            }
+            if grid[tx][ty].walkable != grid[tx][ty + 1].walkable {
+                edges.push(((right - tile_size, bottom), (right, bottom)));
+            }
+            for (a, b) in edges {
+                let distance = distance_to_segment(point, a, b);
+                if closest.is_none_or(|(_, best)| distance < best) {
+                    let length_sq = (b.0 - a.0).powi(2) + (b.1 - a.1).powi(2);
+                    let t = (((point.0 - a.0) * (b.0 - a.0) + (point.1 - a.1) * (b.1 - a.1))
+                        / length_sq)
+                        .clamp(0.0, 1.0);
+                    closest = Some(((a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1)), distance));
+                }
+            }
+        }
+    }
+    match closest {
+        Some((on_boundary, distance)) if distance > tolerance => (
+            on_boundary.0 + (point.0 - on_boundary.0) * tolerance / distance,
+            on_boundary.1 + (point.1 - on_boundary.1) * tolerance / distance,
+        ),
+        _ => point,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::maps::define_floor_patterns;
+    use crate::area_gen::polygons::validate_shapes;
+    use crate::area_gen::test_helpers::grid_of;
+    use crate::area_gen::{generate_area, GenerationConfig, GenerationRequest};
+
+    #[test]
+    fn smoothed_contours_stay_valid_and_within_tolerance() {
+        let extractions = [
+            (ContourSmoothing::None, 0.0),
+            (ContourSmoothing::Chaikin(3), 10.0),
+            (ContourSmoothing::Chaikin(4), 3.0),
+            (ContourSmoothing::CatmullRom(4), 15.0),
+            (ContourSmoothing::CatmullRom(8), 5.0),
+        ];
+        for map_index in 0..define_floor_patterns().len() {
+            for (smoothing, tolerance) in extractions {
+                let config = GenerationConfig {
+                    contour_extraction: ContourExtraction::MarchingSquares {
+                        smoothing,
+                        tolerance,
+                    },
+                    ..GenerationConfig::headless()
+                };
+                let area = generate_area(&GenerationRequest::new(map_index, 7), &config);
+                assert_eq!(validate_shapes(&area.oob_polygons), Ok(()));
+
+                let grid = grid_of(&area);
+                for shape in &area.oob_polygons {
+                    for point in &shape.points {
+                        let point = (point.0 as f64, point.1 as f64);
+                        let clamped =
+                            clamp_to_boundary(&grid, point, tolerance as f64, area.tile_size);
+                        assert!((clamped.0 - point.0).abs() + (clamped.1 - point.1).abs() < 1e-2);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn smoothed_contours_stay_within_half_a_tile_of_the_tile_boundary() {
+        for map_index in 0..define_floor_patterns().len() {
+            for (smoothing, tolerance) in [
+                (ContourSmoothing::None, 30.0),
+                (ContourSmoothing::Chaikin(4), 30.0),
+                (ContourSmoothing::CatmullRom(8), 30.0),
+            ] {
+                let config = GenerationConfig {
+                    contour_extraction: ContourExtraction::MarchingSquares {
+                        smoothing,
+                        tolerance,
+                    },
+                    ..GenerationConfig::headless()
+                };
+                let area = generate_area(&GenerationRequest::new(map_index, 7), &config);
+                let grid = grid_of(&area);
+                let tile_size = area.tile_size as f64;
+                // the tile edges between floor and oob that the staircase follows
+                let boundary_edges = |x: usize, y: usize| {
+                    let (right, bottom) = (x as f64 * tile_size, y as f64 * tile_size);
+                    let mut edges = Vec::new();
+                    if x + 1 < grid.len() && grid[x][y].walkable != grid[x + 1][y].walkable {
+                        edges.push(((right, bottom - tile_size), (right, bottom)));
+                    }
+                    if y + 1 < grid[0].len() && grid[x][y].walkable != grid[x][y + 1].walkable {
+                        edges.push(((right - tile_size, bottom), (right, bottom)));
+                    }
+                    edges
+                };
+                for point in area.oob_polygons.iter().flat_map(|shape| &shape.points) {
+                    let point = (point.0 as f64, point.1 as f64);
+                    let tile = (
+                        (point.0 / tile_size) as usize + 1,
+                        (point.1 / tile_size) as usize + 1,
+                    );
+                    // half a tile never reaches past the neighbouring tiles
+                    let distance = (tile.0.saturating_sub(2)..=tile.0 + 1)
+                        .flat_map(|x| (tile.1.saturating_sub(2)..=tile.1 + 1).map(move |y| (x, y)))
+                        .filter(|(x, y)| *x < grid.len() && *y < grid[0].len())
+                        .flat_map(|(x, y)| boundary_edges(x, y))
+                        .map(|(a, b)| distance_to_segment(point, a, b))
+                        .fold(f64::INFINITY, f64::min);
+                    assert!(
+                        distance <= tile_size / 2.0 + 1e-3,
+                        "map {} : {:?} is {} from the tile boundary",
+                        map_index,
+                        point,
+                        distance
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn folded_contours_go_back_to_the_tile_boundary() {
+        let square = |x: f64| vec![(x, 0.0), (x, 60.0), (x + 60.0, 60.0), (x + 60.0, 0.0)];
+        let unsmoothed = vec![square(0.0), square(120.0), square(200.0), square(400.0)];
+        let mut contours = vec![
+            // folded over itself
+            vec![(0.0, 0.0), (60.0, 60.0), (60.0, 0.0), (0.0, 60.0)],
+            // both sides of a pinch pulled into each other
+            vec![(120.0, 0.0), (120.0, 60.0), (195.0, 60.0), (195.0, 0.0)],
+            vec![(185.0, 0.0), (185.0, 60.0), (260.0, 60.0), (260.0, 0.0)],
+            // rounded, far from the others
+            vec![
+                (410.0, 0.0),
+                (400.0, 30.0),
+                (410.0, 60.0),
+                (450.0, 60.0),
+                (460.0, 30.0),
+            ],
+        ];
+        let kept = contours[3].clone();
+        revert_folded(&mut contours, &unsmoothed, true);
+        assert_eq!(contours[..3], unsmoothed[..3]);
+        assert_eq!(contours[3], kept);
+
+        // without smoothing there is nothing to go back to
+        let mut crossing = vec![square(0.0), square(30.0)];
+        revert_folded(&mut crossing, &[square(0.0), square(60.0)], false);
+        assert_eq!(crossing[1], square(30.0));
+    }
+}
@@ -0,0 +1,161 @@
+// Minimum corridor width: widen the passages of the walkable area narrower than a map minimum
+use super::maps::TileType;
+use super::smoothing::{fix_diagonal_pinches, walkable_bounds, walkable_mask};
+use super::{add_tile, Grid};
+
+// Make sure every floor tile belongs to a width x width square of floor,
+// by stamping a square of floor on each tile that does not, returns the number of tiles added
+pub fn enforce_min_corridor_width(grid: &mut Grid, width: usize) -> usize {
+    if width < 2 {
+        return 0;
+    }
+    let w = width as i32;
+    let mut changed = 0;
+    // filling the corner contacts between stamped squares can leave new narrow tiles
+    loop {
+        let narrow = narrow_tiles(grid, width);
+        if narrow.is_empty() {
+            break;
+        }
+        // stamp a square of floor centered on each narrow tile
+        let mut stamped = 0;
+        for (x, y) in narrow {
+            for dx in 0..w {
+                for dy in 0..w {
+                    let (tile_x, tile_y) = (x as i32 - (w / 2) + dx, y as i32 - (w / 2) + dy);
+                    // squares on the edge of the grid are cut
+                    if tile_x < 0
+                        || tile_y < 0
+                        || tile_x as usize >= grid.len()
+                        || tile_y as usize >= grid[0].len()
+                    {
+                        continue;
+                    }
+                    let (tile_x, tile_y) = (tile_x as usize, tile_y as usize);
+                    if !grid[tile_x][tile_y].walkable {
+                        add_tile(grid, tile_x, tile_y, TileType::Floor, true, false);
+                        stamped += 1;
+                    }
+                }
+            }
+        }
+        // only cut squares are left, they can not be widened
+        if stamped == 0 {
+            break;
+        }
+        // stamping squares next to each other can leave corner contacts
+        changed += stamped + fix_diagonal_pinches(grid);
+    }
+    changed
+}
+
+// Floor tiles that are not part of any width x width square of floor
+pub fn narrow_tiles(grid: &Grid, width: usize) -> Vec<(usize, usize)> {
+    let w = width as i32;
+    let mask = walkable_mask(grid);
+    let Some((min, max)) = walkable_bounds(&mask, w) else {
+        return Vec::new();
+    };
+    // fits[x][y] is true when the square with top left corner (x, y) is only floor
+    let floor_sum = prefix_sum(&mask, min, max);
+    let mut fits = vec![vec![false; mask[0].len()]; mask.len()];
+    for x in min.0..=(max.0 - w + 1) {
+        for y in min.1..=(max.1 - w + 1) {
+            fits[x as usize][y as usize] =
+                area_sum(&floor_sum, min, (x, y), (x + w - 1, y + w - 1)) == w * w;
+        }
+    }
+    // a floor tile is wide enough when one of the squares containing it fits
+    let fits_sum = prefix_sum(&fits, min, max);
+    let mut narrow = Vec::new();
+    for x in min.0..=max.0 {
+        for y in min.1..=max.1 {
+            if mask[x as usize][y as usize]
+                && area_sum(
+                    &fits_sum,
+                    min,
+                    ((x - w + 1).max(min.0), (y - w + 1).max(min.1)),
+                    (x, y),
+                ) == 0
+            {
+                narrow.push((x as usize, y as usize));
+            }
+        }
+    }
+    narrow
+}
+
+// 2D prefix sum of the mask over the bounds, sum[i][j] covers min..(min + (i, j))
+fn prefix_sum(mask: &[Vec<bool>], min: (i32, i32), max: (i32, i32)) -> Vec<Vec<i32>> {
+    let width = (max.0 - min.0 + 2) as usize;
+    let height = (max.1 - min.1 + 2) as usize;
+    let mut sum = vec![vec![0; height]; width];
+    for i in 1..width {
+        for j in 1..height {
+            let value = mask[min.0 as usize + i - 1][min.1 as usize + j - 1] as i32;
+            sum[i][j] = value + sum[i - 1][j] + sum[i][j - 1] - sum[i - 1][j - 1];
+        }
+    }
+    sum
+}
+
+// Number of set tiles in the inclusive rectangle from..=to
+fn area_sum(sum: &[Vec<i32>], min: (i32, i32), from: (i32, i32), to: (i32, i32)) -> i32 {
+    let x0 = (from.0 - min.0) as usize;
+    let y0 = (from.1 - min.1) as usize;
+    let x1 = (to.0 - min.0 + 1) as usize;
+    let y1 = (to.1 - min.1 + 1) as usize;
+    sum[x1][y1] - sum[x0][y1] - sum[x1][y0] + sum[x0][y0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::maps::define_floor_patterns;
+    use crate::area_gen::polygons::validate_shapes;
+    use crate::area_gen::test_helpers::{generate, grid_from, grid_of};
+
+    #[test]
+    fn narrow_passages_are_widened() {
+        let mut grid = grid_from(&[
+            "##############",
+            "##############",
+            "##....########",
+            "##....#....###",
+            "##.........###",
+            "##....#....###",
+            "##############",
+            "##############",
+        ]);
+        assert_eq!(narrow_tiles(&grid, 3), [(6, 4)]);
+        assert!(enforce_min_corridor_width(&mut grid, 3) > 0);
+        assert!(narrow_tiles(&grid, 3).is_empty());
+        // nothing left to widen
+        assert_eq!(enforce_min_corridor_width(&mut grid, 3), 0);
+    }
+
+    #[test]
+    fn squares_are_cut_on_the_grid_edge() {
+        let mut grid = grid_from(&["#.#####", "#.#####", "#......", "#######"]);
+        enforce_min_corridor_width(&mut grid, 4);
+        assert!(grid[1][1].walkable && grid[6][2].walkable);
+    }
+
+    #[test]
+    fn generated_areas_respect_the_map_width() {
+        for (map_index, map) in define_floor_patterns().iter().enumerate() {
+            for seed in [1, 2, 3] {
+                let area = generate(map_index, seed);
+                let grid = grid_of(&area);
+                assert_eq!(
+                    narrow_tiles(&grid, map.min_corridor_width),
+                    [],
+                    "{} seed {}",
+                    map.name,
+                    seed
+                );
+                assert_eq!(validate_shapes(&area.oob_polygons), Ok(()));
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+// Multi floor dungeons: a stack of areas linked by stairs
+use super::landmarks::LandmarkKind;
+use super::tiers::AreaModifier;
+use super::{
+    derive_seed, generate_area, AreaGenerationOutput, GenerationConfig, GenerationRequest, Rerolls,
+};
+
+#[derive(Clone)]
+pub struct DungeonRequest {
+    pub seed: u64,
+    // map of each floor from the top one, the same index can be repeated
+    pub floors: Vec<usize>,
+    pub tier: u32,
+    pub modifiers: Vec<AreaModifier>,
+}
+
+// The stairs down of a floor are its exit, the stairs up are the player spawn.
+// Floors are placed so the stairs up of a floor are right under the stairs down of the one above.
+pub struct DungeonFloor {
+    pub area: AreaGenerationOutput,
+    // position of the floor in pixels, relative to the top floor
+    pub offset: (i32, i32),
+    // in pixels of the floor, none on the top floor
+    pub stairs_up: Option<(i32, i32)>,
+    // none on the bottom floor
+    pub stairs_down: Option<(i32, i32)>,
+}
+
+pub struct DungeonOutput {
+    pub seed: u64,
+    pub floors: Vec<DungeonFloor>,
+}
+
+impl DungeonRequest {
+    // Request of one floor, its seed is derived from the dungeon seed and its level so the
+    // floors of neighbouring dungeon seeds do not share layouts
+    pub fn floor_request(&self, level: usize) -> GenerationRequest {
+        GenerationRequest {
+            map_index: self.floors[level],
+            seed: derive_seed(self.seed, level as u64),
+            tier: self.tier,
+            modifiers: self.modifiers.clone(),
+            entry: None,
+            exit: None,
+            side_gates: Vec::new(),
+            rerolls: Rerolls::default(),
+        }
+    }
+}
+
+pub fn generate_dungeon(request: &DungeonRequest, config: &GenerationConfig) -> DungeonOutput {
+    let mut floors: Vec<DungeonFloor> = Vec::new();
+    for level in 0..request.floors.len() {
+        let area = generate_area(&request.floor_request(level), config);
+        let stairs_up = (level > 0).then_some(area.player_spawn_position);
+        let stairs_down = if level + 1 < request.floors.len() {
+            area.landmarks
+                .iter()
+                .find(|landmark| landmark.kind == LandmarkKind::Exit)
+                .map(|landmark| (landmark.point.0 as i32, landmark.point.1 as i32))
+        } else {
+            None
+        };
+        // a floor above without an exit leaves this one where it is
+        let offset = match (floors.last(), stairs_up) {
+            (
+                Some(DungeonFloor {
+                    offset,
+                    stairs_down: Some(down),
+                    ..
+                }),
+                Some(up),
+            ) => (offset.0 + down.0 - up.0, offset.1 + down.1 - up.1),
+            (Some(above), _) => above.offset,
+            (None, _) => (0, 0),
+        };
+        floors.push(DungeonFloor {
+            area,
+            offset,
+            stairs_up,
+            stairs_down,
+        });
+    }
+
+    DungeonOutput {
+        seed: request.seed,
+        floors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(seed: u64) -> DungeonRequest {
+        DungeonRequest {
+            seed,
+            floors: vec![4, 4, 1],
+            tier: 1,
+            modifiers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn stairs_line_up_between_floors() {
+        for seed in [1, 2, 3] {
+            let dungeon = generate_dungeon(&request(seed), &GenerationConfig::headless());
+            assert_eq!(dungeon.floors.len(), 3);
+            assert_eq!(dungeon.floors[0].stairs_up, None);
+            assert_eq!(dungeon.floors.last().unwrap().stairs_down, None);
+            for pair in dungeon.floors.windows(2) {
+                let (above, below) = (&pair[0], &pair[1]);
+                let down = above
+                    .stairs_down
+                    .expect("every floor but the last has stairs down");
+                let up = below.stairs_up.unwrap();
+                assert_eq!(
+                    (below.offset.0 + up.0, below.offset.1 + up.1),
+                    (above.offset.0 + down.0, above.offset.1 + down.1),
+                    "seed {}",
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn neighbouring_dungeons_do_not_share_floors() {
+        for seed in [0, 1, 1000, u64::MAX - 1] {
+            let (request, next) = (request(seed), request(seed.wrapping_add(1)));
+            let seeds: Vec<u64> = (0..3)
+                .flat_map(|level| {
+                    [
+                        request.floor_request(level).seed,
+                        next.floor_request(level).seed,
+                    ]
+                })
+                .collect();
+            for (index, floor_seed) in seeds.iter().enumerate() {
+                assert!(!seeds[index + 1..].contains(floor_seed), "seed {}", seed);
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+// Exits, waypoints and checkpoints, placed on the floor once the layout is final
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+
+use super::maps::TileType;
+use super::pathfinding::Path;
+use super::{tile_center, Grid};
+
+// landmarks are kept this many tiles apart
+const MIN_SPACING: f32 = 10.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum LandmarkKind {
+    // to the next area
+    Exit,
+    Waypoint,
+    Checkpoint,
+}
+
+#[derive(Clone, Copy)]
+pub enum Placement {
+    // the floor tile with the longest walk from the spawn
+    FarthestFromSpawn,
+    // a random floor tile between half and all of max_distance tiles of walk from the spawn
+    NearSpawn { max_distance: f32 },
+    // one every interval tiles along the path from the spawn to the boss
+    AlongCriticalPath { interval: f32 },
+}
+
+#[derive(Clone, Copy)]
+pub struct LandmarkRule {
+    pub kind: LandmarkKind,
+    pub placement: Placement,
+}
+
+pub struct Landmark {
+    pub kind: LandmarkKind,
+    pub tile: (u32, u32),
+    // center of the tile in pixels
+    pub point: (f32, f32),
+}
+
+impl LandmarkKind {
+    pub fn tile_type(&self) -> TileType {
+        match self {
+            LandmarkKind::Exit => TileType::Exit,
+            LandmarkKind::Waypoint => TileType::Waypoint,
+            LandmarkKind::Checkpoint => TileType::Checkpoint,
+        }
+    }
+}
+
+// Rules that can not be satisfied are skipped, exit is the tile of a required exit gate
+pub(crate) fn place_landmarks(
+    grid: &mut Grid,
+    rules: &[LandmarkRule],
+    // path from the spawn to the boss
+    critical_path: Option<&Path>,
+    exit: Option<(usize, usize)>,
+    spawn_distances: &[f32],
+    rng: &mut ChaCha8Rng,
+    tile_size: i32,
+) -> Vec<Landmark> {
+    let height = grid[0].len();
+    let mut placed: Vec<(LandmarkKind, (usize, usize))> = Vec::new();
+    let free = |grid: &Grid, placed: &[(LandmarkKind, (usize, usize))], x: usize, y: usize| {
+        grid[x][y].walkable
+            && grid[x][y].tile_type == TileType::Floor
+            && placed.iter().all(|(_, other)| {
+                let dx = other.0 as f32 - x as f32;
+                let dy = other.1 as f32 - y as f32;
+                dx * dx + dy * dy >= MIN_SPACING * MIN_SPACING
+            })
+    };
+    // the exit gate asked by the request replaces the exit rules of the map
+    if let Some(tile) = exit {
+        placed.push((LandmarkKind::Exit, tile));
+    }
+    for rule in rules
+        .iter()
+        .filter(|rule| exit.is_none() || rule.kind != LandmarkKind::Exit)
+    {
+        match rule.placement {
+            Placement::FarthestFromSpawn => {
+                let mut farthest: Option<((usize, usize), f32)> = None;
+                for x in 0..grid.len() {
+                    for y in 0..height {
+                        let distance = spawn_distances[x * height + y];
+                        if distance.is_finite()
+                            && farthest.is_none_or(|(_, best)| distance > best)
+                            && free(grid, &placed, x, y)
+                        {
+                            farthest = Some(((x, y), distance));
+                        }
+                    }
+                }
+                if let Some((tile, _)) = farthest {
+                    placed.push((rule.kind, tile));
+                }
+            }
+            Placement::NearSpawn { max_distance } => {
+                let mut candidates = Vec::new();
+                for x in 0..grid.len() {
+                    for y in 0..height {
+                        let distance = spawn_distances[x * height + y];
+                        if distance >= max_distance / 2.0
+                            && distance <= max_distance
+                            && free(grid, &placed, x, y)
+                        {
+                            candidates.push((x, y));
+                        }
+                    }
+                }
+                if let Some(tile) = candidates.choose(rng) {
+                    placed.push((rule.kind, *tile));
+                }
+            }
+            Placement::AlongCriticalPath { interval } => {
+                let Some(path) = critical_path else {
+                    continue;
+                };
+                let mut walked = 0.0;
+                let mut next = interval;
+                for pair in path.tiles.windows(2) {
+                    let diagonal = pair[0].0 != pair[1].0 && pair[0].1 != pair[1].1;
+                    walked += if diagonal {
+                        std::f32::consts::SQRT_2
+                    } else {
+                        1.0
+                    };
+                    // none right before the boss
+                    if walked >= next && path.cost - walked >= interval / 2.0 {
+                        next += interval;
+                        let (x, y) = pair[1];
+                        if free(grid, &placed, x, y) {
+                            placed.push((rule.kind, (x, y)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    placed
+        .into_iter()
+        .map(|(kind, tile)| {
+            mark_landmark(grid, kind, tile);
+            Landmark {
+                kind,
+                tile: (tile.0 as u32, tile.1 as u32),
+                point: tile_center(tile, tile_size),
+            }
+        })
+        .collect()
+}
+
+// 3 x 3 marker on the floor around the landmark, packs stay a tile further away
+fn mark_landmark(grid: &mut Grid, kind: LandmarkKind, tile: (usize, usize)) {
+    for x in tile.0.saturating_sub(2)..(tile.0 + 3).min(grid.len()) {
+        for y in tile.1.saturating_sub(2)..(tile.1 + 3).min(grid[0].len()) {
+            grid[x][y].spawnable = false;
+            let marker = x.abs_diff(tile.0) <= 1 && y.abs_diff(tile.1) <= 1;
+            if marker && grid[x][y].walkable && grid[x][y].tile_type == TileType::Floor {
+                grid[x][y].tile_type = kind.tile_type();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::area_gen::maps::define_floor_patterns;
+    use crate::area_gen::pathfinding::{distance_map, find_path, PathOptions};
+    use crate::area_gen::test_helpers::{generate, grid_from};
+
+    const SPAWN: (usize, usize) = (1, 1);
+
+    // open room of 40 by 20 floor tiles
+    fn room() -> Grid {
+        let wall = "#".repeat(42);
+        let floor = format!("#{}#", ".".repeat(40));
+        let mut rows = vec![wall.as_str()];
+        rows.extend(std::iter::repeat_n(floor.as_str(), 20));
+        rows.push(wall.as_str());
+        grid_from(&rows)
+    }
+
+    fn place(seed: u64) -> (Vec<Landmark>, Vec<f32>) {
+        let mut grid = room();
+        let options = PathOptions::default();
+        let distances = distance_map(&(&grid, 10), SPAWN, &options);
+        // straight along the top wall
+        let path = find_path(&(&grid, 10), SPAWN, (40, 1), &options).unwrap();
+        let rules = [
+            LandmarkRule {
+                kind: LandmarkKind::Exit,
+                placement: Placement::FarthestFromSpawn,
+            },
+            LandmarkRule {
+                kind: LandmarkKind::Checkpoint,
+                placement: Placement::AlongCriticalPath { interval: 15.0 },
+            },
+            LandmarkRule {
+                kind: LandmarkKind::Waypoint,
+                placement: Placement::NearSpawn { max_distance: 12.0 },
+            },
+        ];
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let landmarks = place_landmarks(
+            &mut grid,
+            &rules,
+            Some(&path),
+            None,
+            &distances,
+            &mut rng,
+            10,
+        );
+        (landmarks, distances)
+    }
+
+    fn of_kind(landmarks: &[Landmark], kind: LandmarkKind) -> Vec<(u32, u32)> {
+        landmarks
+            .iter()
+            .filter(|landmark| landmark.kind == kind)
+            .map(|landmark| landmark.tile)
+            .collect()
+    }
+
+    fn distance(distances: &[f32], tile: (u32, u32)) -> f32 {
+        distances[tile.0 as usize * 22 + tile.1 as usize]
+    }
+
+    #[test]
+    fn exit_is_the_farthest_walk_from_the_spawn() {
+        let (landmarks, distances) = place(1);
+        let farthest = distances
+            .iter()
+            .copied()
+            .filter(|distance| distance.is_finite())
+            .fold(0.0, f32::max);
+        let exits = of_kind(&landmarks, LandmarkKind::Exit);
+        assert_eq!(exits, vec![(40, 20)]);
+        assert_eq!(distance(&distances, exits[0]), farthest);
+    }
+
+    #[test]
+    fn waypoint_is_within_the_range() {
+        for seed in 0..20 {
+            let (landmarks, distances) = place(seed);
+            let waypoints = of_kind(&landmarks, LandmarkKind::Waypoint);
+            assert_eq!(waypoints.len(), 1, "seed {}", seed);
+            let walk = distance(&distances, waypoints[0]);
+            assert!((6.0..=12.0).contains(&walk), "seed {}: {}", seed, walk);
+        }
+    }
+
+    #[test]
+    fn checkpoints_are_spaced_along_the_critical_path() {
+        let (landmarks, _) = place(1);
+        // 39 tiles of path, none in the last half interval before the boss
+        assert_eq!(
+            of_kind(&landmarks, LandmarkKind::Checkpoint),
+            vec![(16, 1), (31, 1)]
+        );
+    }
+
+    #[test]
+    fn landmarks_keep_their_spacing() {
+        let mut areas: Vec<Vec<Landmark>> = (0..20).map(|seed| place(seed).0).collect();
+        for map_index in 0..define_floor_patterns().len() {
+            areas.push(generate(map_index, 1).landmarks);
+        }
+        for landmarks in &areas {
+            for (index, first) in landmarks.iter().enumerate() {
+                for second in &landmarks[index + 1..] {
+                    let dx = first.tile.0 as f32 - second.tile.0 as f32;
+                    let dy = first.tile.1 as f32 - second.tile.1 as f32;
+                    assert!((dx * dx + dy * dy).sqrt() >= MIN_SPACING);
+                }
+            }
+        }
+    }
+}
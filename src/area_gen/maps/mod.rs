@@ -1,3 +1,10 @@
+use super::landmarks::{LandmarkKind, LandmarkRule, Placement};
+use super::mobs::{
+    Curve, DifficultyGradient, Formation, MonsterEntry, MonsterModifier, MonsterTable,
+    PackComposition, PackDistribution, RarityRoll,
+};
+use super::smoothing::{SmoothingStep, StructuringElement};
+
 #[derive(Clone)]
 pub struct FloorPattern {
     // odds: f32,
@@ -8,7 +15,7 @@ pub struct FloorPattern {
     pub generation_area_size: (i32, i32),
 }
 #[allow(dead_code)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum TileType {
     Floor,
     Wall,
@@ -18,10 +25,15 @@ pub enum TileType {
     Water,
     Forest,
     Angle,
+    Exit,
+    Waypoint,
+    Checkpoint,
 }
 #[derive(Clone)]
 pub struct MobPack {
     pub tile_coords: (usize, usize),
+    // progress from the spawn (0.0) to the boss (1.0)
+    pub difficulty: f64,
 }
 
 #[derive(Clone)]
@@ -38,7 +50,24 @@ pub struct Map {
     pub name: String,
     pub oob_type: TileType,
     pub biomes: Vec<FloorPattern>,
+    // packs per walkable tile
     pub density: f64,
+    pub pack_distribution: PackDistribution,
+    // no pack closer than this to the player spawn, in tiles of walking distance. The maps
+    // have no hazards and the landmarks are harmless, a waypoint can be right by the spawn
+    pub spawn_safe_radius: f64,
+    // applied in order once the walkable layout is generated, a map without steps keeps
+    // the layout its seeds always had
+    pub smoothing: Vec<SmoothingStep>,
+    // passages narrower than this are widened after smoothing, 0 or 1 to disable
+    pub min_corridor_width: usize,
+    // pixel sizes used instead of the ones of the generation config
+    pub tile_size: Option<i32>,
+    pub mob_size: Option<i32>,
+    pub monsters: MonsterTable,
+    pub difficulty: DifficultyGradient,
+    // placed in order, each one at least a few tiles from the previous ones
+    pub landmarks: Vec<LandmarkRule>,
 }
 pub fn define_floor_patterns() -> Vec<Map> {
     //------------------------------------------------------//
@@ -106,6 +135,147 @@ pub fn define_floor_patterns() -> Vec<Map> {
 
         generation_area_size: (345, 345),
     };
+    //------------------------------------------------------//
+    //                Define Monster Tables                 //
+    //------------------------------------------------------//
+    let rare = RarityRoll {
+        chance: 0.1,
+        modifier_count: (1, 2),
+    };
+    let unique = RarityRoll {
+        chance: 0.01,
+        modifier_count: (3, 4),
+    };
+    let all_modifiers = vec![
+        MonsterModifier::Fast,
+        MonsterModifier::ExtraHealth,
+        MonsterModifier::ExtraDamage,
+        MonsterModifier::FireAura,
+        MonsterModifier::FrostAura,
+        MonsterModifier::Regenerating,
+        MonsterModifier::Teleporting,
+        MonsterModifier::Splitting,
+    ];
+    let island_monsters = MonsterTable {
+        packs: vec![
+            PackComposition {
+                weight: 3,
+                leaders: vec![],
+                escort: vec![
+                    MonsterEntry::new("crab", 3),
+                    MonsterEntry::new("seagull", 1),
+                ],
+                size: (3, 6),
+                formation: Formation::Scattered,
+            },
+            PackComposition {
+                weight: 1,
+                leaders: vec![String::from("siren")],
+                escort: vec![MonsterEntry::new("drowned_sailor", 1)],
+                size: (3, 5),
+                formation: Formation::Ring,
+            },
+        ],
+        rare,
+        unique,
+        jitter: 0.2,
+        modifiers: all_modifiers.clone(),
+    };
+    let rock_monsters = MonsterTable {
+        packs: vec![
+            PackComposition {
+                weight: 4,
+                leaders: vec![],
+                escort: vec![
+                    MonsterEntry::new("goblin", 4),
+                    MonsterEntry::new("goblin_archer", 2),
+                ],
+                size: (3, 7),
+                formation: Formation::Cluster,
+            },
+            PackComposition {
+                weight: 2,
+                leaders: vec![String::from("goblin_shaman")],
+                escort: vec![MonsterEntry::new("goblin", 1)],
+                size: (3, 6),
+                formation: Formation::Ring,
+            },
+            PackComposition {
+                weight: 1,
+                leaders: vec![String::from("rock_golem")],
+                escort: vec![],
+                size: (1, 1),
+                formation: Formation::Cluster,
+            },
+        ],
+        rare,
+        unique,
+        jitter: 0.2,
+        modifiers: all_modifiers.clone(),
+    };
+    let desert_monsters = MonsterTable {
+        packs: vec![
+            PackComposition {
+                weight: 3,
+                leaders: vec![],
+                escort: vec![
+                    MonsterEntry::new("scorpion", 2),
+                    MonsterEntry::new("sand_snake", 1),
+                ],
+                size: (2, 5),
+                formation: Formation::Scattered,
+            },
+            PackComposition {
+                weight: 1,
+                leaders: vec![String::from("mummy_priest")],
+                escort: vec![MonsterEntry::new("mummy", 1)],
+                size: (4, 8),
+                formation: Formation::Line,
+            },
+        ],
+        rare,
+        unique,
+        jitter: 0.2,
+        // fire is the natural state of the desert
+        modifiers: all_modifiers
+            .iter()
+            .copied()
+            .filter(|modifier| *modifier != MonsterModifier::FireAura)
+            .collect(),
+    };
+    let forest_monsters = MonsterTable {
+        packs: vec![
+            PackComposition {
+                weight: 3,
+                leaders: vec![],
+                escort: vec![MonsterEntry::new("wolf", 1)],
+                size: (3, 6),
+                formation: Formation::Cluster,
+            },
+            PackComposition {
+                weight: 2,
+                leaders: vec![String::from("druid")],
+                escort: vec![MonsterEntry::new("bear", 1), MonsterEntry::new("wolf", 2)],
+                size: (2, 4),
+                formation: Formation::Ring,
+            },
+            PackComposition {
+                weight: 2,
+                leaders: vec![],
+                escort: vec![
+                    MonsterEntry::new("spider", 3),
+                    MonsterEntry::new("spider_queen", 1),
+                ],
+                size: (4, 8),
+                formation: Formation::Scattered,
+            },
+        ],
+        rare,
+        unique,
+        jitter: 0.2,
+        modifiers: all_modifiers.clone(),
+    };
+
     //------------------------------------------------------//
     //                Define Maps Content                   //
     //------------------------------------------------------//
@@ -116,6 +286,44 @@ pub fn define_floor_patterns() -> Vec<Map> {
             oob_type: TileType::Water,
             biomes: vec![many_tiny_all_dir.clone(), small_all_dir.clone()],
             density: 0.02,
+            pack_distribution: PackDistribution::PoissonDisc { min_distance: 4.0 },
+            spawn_safe_radius: 12.0,
+            smoothing: vec![
+                // round the coast line
+                SmoothingStep::Close(StructuringElement::Disk(2)),
+                SmoothingStep::FixDiagonalPinches,
+            ],
+            min_corridor_width: 3,
+            tile_size: None,
+            mob_size: None,
+            monsters: island_monsters,
+            difficulty: DifficultyGradient {
+                density: Curve {
+                    start: 0.6,
+                    end: 1.4,
+                    exponent: 1.0,
+                },
+                pack_size: Curve {
+                    start: 0.8,
+                    end: 1.3,
+                    exponent: 1.0,
+                },
+                rare_chance: Curve {
+                    start: 0.5,
+                    end: 2.0,
+                    exponent: 2.0,
+                },
+            },
+            landmarks: vec![
+                LandmarkRule {
+                    kind: LandmarkKind::Waypoint,
+                    placement: Placement::NearSpawn { max_distance: 15.0 },
+                },
+                LandmarkRule {
+                    kind: LandmarkKind::Exit,
+                    placement: Placement::FarthestFromSpawn,
+                },
+            ],
         },
         Map {
             name: String::from("Ledge"),
@@ -125,12 +333,85 @@ pub fn define_floor_patterns() -> Vec<Map> {
                 long_path_bottom_right_dir.clone(),
             ],
             density: 0.1,
+            pack_distribution: PackDistribution::ScanOrder,
+            spawn_safe_radius: 12.0,
+            smoothing: vec![
+                // square cuts, like the quarry walls
+                SmoothingStep::Close(StructuringElement::Square(1)),
+                SmoothingStep::FixDiagonalPinches,
+            ],
+            min_corridor_width: 2,
+            tile_size: None,
+            mob_size: None,
+            monsters: rock_monsters.clone(),
+            difficulty: DifficultyGradient {
+                density: Curve::FLAT,
+                pack_size: Curve {
+                    start: 0.7,
+                    end: 1.5,
+                    exponent: 1.5,
+                },
+                rare_chance: Curve {
+                    start: 0.5,
+                    end: 2.5,
+                    exponent: 2.0,
+                },
+            },
+            landmarks: vec![
+                LandmarkRule {
+                    kind: LandmarkKind::Exit,
+                    placement: Placement::FarthestFromSpawn,
+                },
+                LandmarkRule {
+                    kind: LandmarkKind::Checkpoint,
+                    placement: Placement::AlongCriticalPath { interval: 60.0 },
+                },
+            ],
         },
         Map {
             name: String::from("Desert"),
             oob_type: TileType::Wall,
             biomes: vec![long_path_bottom_right_dir.clone(), large_all_dir.clone()],
             density: 0.008,
+            pack_distribution: PackDistribution::PoissonDisc { min_distance: 8.0 },
+            spawn_safe_radius: 12.0,
+            smoothing: vec![
+                // dunes: no thin spikes of sand, soft corners
+                SmoothingStep::Open(StructuringElement::Disk(1)),
+                SmoothingStep::Close(StructuringElement::Disk(2)),
+                SmoothingStep::FixDiagonalPinches,
+            ],
+            min_corridor_width: 3,
+            tile_size: None,
+            mob_size: None,
+            monsters: desert_monsters,
+            difficulty: DifficultyGradient {
+                density: Curve {
+                    start: 0.5,
+                    end: 1.5,
+                    exponent: 1.0,
+                },
+                pack_size: Curve::FLAT,
+                rare_chance: Curve {
+                    start: 1.0,
+                    end: 3.0,
+                    exponent: 3.0,
+                },
+            },
+            landmarks: vec![
+                LandmarkRule {
+                    kind: LandmarkKind::Waypoint,
+                    placement: Placement::NearSpawn { max_distance: 20.0 },
+                },
+                LandmarkRule {
+                    kind: LandmarkKind::Exit,
+                    placement: Placement::FarthestFromSpawn,
+                },
+                LandmarkRule {
+                    kind: LandmarkKind::Checkpoint,
+                    placement: Placement::AlongCriticalPath { interval: 120.0 },
+                },
+            ],
         },
         Map {
             name: String::from("Forest"),
@@ -141,6 +422,47 @@ pub fn define_floor_patterns() -> Vec<Map> {
                 small_cross_dir.clone(),
             ],
             density: 0.01,
+            pack_distribution: PackDistribution::PoissonDisc { min_distance: 6.0 },
+            spawn_safe_radius: 12.0,
+            smoothing: vec![
+                SmoothingStep::Close(StructuringElement::Disk(1)),
+                SmoothingStep::FixDiagonalPinches,
+            ],
+            min_corridor_width: 2,
+            tile_size: None,
+            mob_size: None,
+            monsters: forest_monsters,
+            difficulty: DifficultyGradient {
+                density: Curve {
+                    start: 0.8,
+                    end: 1.2,
+                    exponent: 1.0,
+                },
+                pack_size: Curve {
+                    start: 0.8,
+                    end: 1.4,
+                    exponent: 1.0,
+                },
+                rare_chance: Curve {
+                    start: 0.5,
+                    end: 2.0,
+                    exponent: 1.0,
+                },
+            },
+            landmarks: vec![
+                LandmarkRule {
+                    kind: LandmarkKind::Waypoint,
+                    placement: Placement::NearSpawn { max_distance: 15.0 },
+                },
+                LandmarkRule {
+                    kind: LandmarkKind::Exit,
+                    placement: Placement::FarthestFromSpawn,
+                },
+                LandmarkRule {
+                    kind: LandmarkKind::Checkpoint,
+                    placement: Placement::AlongCriticalPath { interval: 80.0 },
+                },
+            ],
         },
         Map {
             name: String::from("Quarry"),
@@ -152,6 +474,43 @@ pub fn define_floor_patterns() -> Vec<Map> {
                 short_path_bottom_right_dir.clone(),
             ],
             density: 0.01,
+            pack_distribution: PackDistribution::PoissonDisc { min_distance: 6.0 },
+            spawn_safe_radius: 12.0,
+            smoothing: vec![
+                SmoothingStep::Close(StructuringElement::Cross(1)),
+                SmoothingStep::FixDiagonalPinches,
+            ],
+            min_corridor_width: 3,
+            tile_size: None,
+            mob_size: None,
+            monsters: rock_monsters,
+            difficulty: DifficultyGradient {
+                density: Curve {
+                    start: 0.7,
+                    end: 1.3,
+                    exponent: 1.0,
+                },
+                pack_size: Curve {
+                    start: 0.8,
+                    end: 1.4,
+                    exponent: 2.0,
+                },
+                rare_chance: Curve {
+                    start: 0.5,
+                    end: 2.0,
+                    exponent: 2.0,
+                },
+            },
+            landmarks: vec![
+                LandmarkRule {
+                    kind: LandmarkKind::Waypoint,
+                    placement: Placement::NearSpawn { max_distance: 15.0 },
+                },
+                LandmarkRule {
+                    kind: LandmarkKind::Exit,
+                    placement: Placement::FarthestFromSpawn,
+                },
+            ],
         },
     ];
     maps
@@ -0,0 +1,668 @@
+// Monster tables of the maps, and rolling of the monsters of each pack
+use std::collections::HashMap;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use super::maps::MobPack;
+use super::{px_to_tile, tile_center, Enemy, Grid};
+
+#[derive(Clone)]
+pub struct MonsterTable {
+    // one composition is picked by weight for each pack
+    pub packs: Vec<PackComposition>,
+    pub rare: RarityRoll,
+    pub unique: RarityRoll,
+    // pool the modifiers of rare and unique monsters are drawn from, without repeats
+    pub modifiers: Vec<MonsterModifier>,
+    // max random offset added to each mob of a formation, in mob sizes
+    pub jitter: f32,
+}
+
+#[derive(Clone)]
+pub struct PackComposition {
+    pub weight: u32,
+    // always in the pack, e.g. one caster
+    pub leaders: Vec<String>,
+    // fill the rest of the pack, picked by weight for each mob
+    pub escort: Vec<MonsterEntry>,
+    // number of mobs in the pack, leaders included, both inclusive
+    pub size: (usize, usize),
+    pub formation: Formation,
+}
+
+// Multiplier going from start to end along the way from the spawn to the boss,
+// progress is raised to the exponent first, 1.0 for a linear ramp
+#[derive(Clone, Copy)]
+pub struct Curve {
+    pub start: f64,
+    pub end: f64,
+    pub exponent: f64,
+}
+
+impl Curve {
+    pub const FLAT: Curve = Curve {
+        start: 1.0,
+        end: 1.0,
+        exponent: 1.0,
+    };
+
+    pub fn at(&self, progress: f64) -> f64 {
+        self.start + (self.end - self.start) * progress.clamp(0.0, 1.0).powf(self.exponent)
+    }
+
+    pub fn max(&self) -> f64 {
+        self.start.max(self.end)
+    }
+}
+
+// How the packs get harder toward the boss
+#[derive(Clone, Copy)]
+pub struct DifficultyGradient {
+    // multiplies the map density
+    pub density: Curve,
+    // multiplies the rolled pack size
+    pub pack_size: Curve,
+    // multiplies the chance of a rare leader
+    pub rare_chance: Curve,
+}
+
+// Where the packs are placed on the map, the number of packs always comes from the map density
+#[derive(Clone, Copy)]
+pub enum PackDistribution {
+    // one pack every few walkable tiles, in x major scan order
+    ScanOrder,
+    // random packs at least min_distance tiles apart, fewer than the density
+    // asks for if they do not fit
+    PoissonDisc { min_distance: f64 },
+}
+
+// How the mobs of a pack are laid out around the pack tile, the leader comes first
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Formation {
+    // packed tightly around the leader
+    Cluster,
+    // leader in the middle, the escort on a circle around it
+    Ring,
+    // side by side in a random direction
+    Line,
+    // spread randomly over a disk
+    Scattered,
+}
+
+#[derive(Clone)]
+pub struct MonsterEntry {
+    pub monster_id: String,
+    pub weight: u32,
+}
+
+// Chance for a pack to be led by a monster of that rarity, and how many modifiers it gets
+#[derive(Clone, Copy)]
+pub struct RarityRoll {
+    pub chance: f64,
+    pub modifier_count: (usize, usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Rarity {
+    Normal,
+    Rare,
+    Unique,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MonsterModifier {
+    Fast,
+    ExtraHealth,
+    ExtraDamage,
+    FireAura,
+    FrostAura,
+    Regenerating,
+    Teleporting,
+    Splitting,
+}
+
+impl MonsterEntry {
+    pub fn new(monster_id: &str, weight: u32) -> MonsterEntry {
+        MonsterEntry {
+            monster_id: String::from(monster_id),
+            weight,
+        }
+    }
+}
+
+impl MonsterTable {
+    // Monster ids of a pack, the first one leads the pack. None when the table can not roll
+    // one: no composition with a weight, or a size range that is upside down
+    fn roll_pack(
+        &self,
+        rng: &mut ChaCha8Rng,
+        size_multiplier: f64,
+    ) -> Option<(Vec<String>, Formation)> {
+        let weights = WeightedIndex::new(self.packs.iter().map(|pack| pack.weight)).ok()?;
+        let composition = &self.packs[weights.sample(rng)];
+        if composition.size.0 > composition.size.1 {
+            return None;
+        }
+        let size = ((rng.gen_range(composition.size.0..=composition.size.1) as f64
+            * size_multiplier)
+            .round() as usize)
+            .max(composition.leaders.len())
+            .max(1);
+        let mut members: Vec<String> = composition.leaders.clone();
+        // an escort without any weight is left out, as if it was empty
+        if let Ok(weights) = WeightedIndex::new(composition.escort.iter().map(|entry| entry.weight))
+        {
+            while members.len() < size {
+                members.push(composition.escort[weights.sample(rng)].monster_id.clone());
+            }
+        }
+        if members.is_empty() {
+            return None;
+        }
+        Some((members, composition.formation))
+    }
+
+    fn roll_rarity(
+        &self,
+        rng: &mut ChaCha8Rng,
+        rare_multiplier: f64,
+    ) -> (Rarity, Vec<MonsterModifier>) {
+        let (rarity, roll) = if rng.gen_bool(self.unique.chance.clamp(0.0, 1.0)) {
+            (Rarity::Unique, self.unique)
+        } else if rng.gen_bool((self.rare.chance * rare_multiplier).clamp(0.0, 1.0)) {
+            (Rarity::Rare, self.rare)
+        } else {
+            return (Rarity::Normal, Vec::new());
+        };
+        let (min, max) = roll.modifier_count;
+        let count = if min <= max {
+            rng.gen_range(min..=max).min(self.modifiers.len())
+        } else {
+            0
+        };
+        let modifiers = self
+            .modifiers
+            .choose_multiple(rng, count)
+            .copied()
+            .collect();
+        (rarity, modifiers)
+    }
+}
+
+pub fn generate_mobs(
+    grid: &Grid,
+    packs: &[MobPack],
+    table: &MonsterTable,
+    gradient: &DifficultyGradient,
+    rng: &mut ChaCha8Rng,
+    tile_size: i32,
+    mob_size: i32,
+) -> Vec<Enemy> {
+    let mut mobs = Vec::new();
+    let mob_size = mob_size as f32;
+    for (pack_index, pack) in packs.iter().enumerate() {
+        let Some((members, formation)) =
+            table.roll_pack(rng, gradient.pack_size.at(pack.difficulty))
+        else {
+            continue;
+        };
+        // only the leader can be rare or unique
+        let (leader_rarity, leader_modifiers) =
+            table.roll_rarity(rng, gradient.rare_chance.at(pack.difficulty));
+        // packs are only placed on floor tiles
+        let center = tile_center(pack.tile_coords, tile_size);
+        let offsets = formation_offsets(formation, members.len(), mob_size, rng);
+        for (index, (monster_id, offset)) in members.into_iter().zip(offsets).enumerate() {
+            let jitter = table.jitter * mob_size;
+            let mut offset = (
+                offset.0 + rng.gen_range(-1.0..=1.0) * jitter,
+                offset.1 + rng.gen_range(-1.0..=1.0) * jitter,
+            );
+            // pull the mob toward the pack tile until it stands on floor,
+            // rounded first as the output is in whole pixels
+            let mut point = ((center.0 + offset.0).round(), (center.1 + offset.1).round());
+            for _ in 0..4 {
+                if is_walkable_px(grid, point, tile_size) {
+                    break;
+                }
+                offset = (offset.0 / 2.0, offset.1 / 2.0);
+                point = ((center.0 + offset.0).round(), (center.1 + offset.1).round());
+            }
+            if !is_walkable_px(grid, point, tile_size) {
+                point = center;
+            }
+
+            let (rarity, modifiers) = if index == 0 {
+                (leader_rarity, leader_modifiers.clone())
+            } else {
+                (Rarity::Normal, Vec::new())
+            };
+            mobs.push(Enemy {
+                point: (point.0 as u32, point.1 as u32),
+                monster_id,
+                rarity,
+                modifiers,
+                pack: pack_index,
+            });
+        }
+    }
+
+    mobs
+}
+
+// Offsets in pixels from the pack center, mobs are mob_size apart
+fn formation_offsets(
+    formation: Formation,
+    count: usize,
+    mob_size: f32,
+    rng: &mut ChaCha8Rng,
+) -> Vec<(f32, f32)> {
+    let rotation = rng.gen_range(0.0..std::f32::consts::TAU);
+    match formation {
+        Formation::Cluster => {
+            // sunflower spiral, evenly packed disk
+            let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+            (0..count)
+                .map(|i| {
+                    let radius = mob_size * 0.6 * (i as f32).sqrt();
+                    let angle = rotation + i as f32 * golden_angle;
+                    (radius * angle.cos(), radius * angle.sin())
+                })
+                .collect()
+        }
+        Formation::Ring => {
+            let escort = count.saturating_sub(1);
+            let radius = formation_radius(formation, count, mob_size);
+            let mut offsets = vec![(0.0, 0.0)];
+            for i in 0..escort {
+                let angle = rotation + i as f32 * std::f32::consts::TAU / escort as f32;
+                offsets.push((radius * angle.cos(), radius * angle.sin()));
+            }
+            offsets.truncate(count);
+            offsets
+        }
+        Formation::Line => {
+            let direction = (rotation.cos(), rotation.sin());
+            // leader in the middle of the line
+            (0..count)
+                .map(|i| {
+                    let side = if i % 2 == 0 { 1.0 } else { -1.0 };
+                    let distance = i.div_ceil(2) as f32 * mob_size * side;
+                    (direction.0 * distance, direction.1 * distance)
+                })
+                .collect()
+        }
+        Formation::Scattered => {
+            let radius = formation_radius(formation, count, mob_size);
+            let mut offsets = vec![(0.0, 0.0)];
+            for _ in 1..count {
+                // square root for a uniform density over the disk
+                let distance = radius * rng.gen_range(0.0f32..1.0).sqrt();
+                let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                offsets.push((distance * angle.cos(), distance * angle.sin()));
+            }
+            offsets.truncate(count);
+            offsets
+        }
+    }
+}
+
+// Distance in pixels from the pack center to the farthest mob of the formation
+fn formation_radius(formation: Formation, count: usize, mob_size: f32) -> f32 {
+    match formation {
+        Formation::Cluster => mob_size * 0.6 * (count.saturating_sub(1) as f32).sqrt(),
+        // big enough for the escort to stand side by side
+        Formation::Ring => {
+            mob_size.max(count.saturating_sub(1) as f32 * mob_size / std::f32::consts::TAU)
+        }
+        Formation::Line => (count.saturating_sub(1)).div_ceil(2) as f32 * mob_size,
+        Formation::Scattered => mob_size * 1.5 * (count as f32).sqrt(),
+    }
+}
+
+fn is_walkable_px(grid: &Grid, point: (f32, f32), tile_size: i32) -> bool {
+    let tile = px_to_tile(point, tile_size);
+    tile.0 >= 0
+        && tile.1 >= 0
+        && (tile.0 as usize) < grid.len()
+        && (tile.1 as usize) < grid[0].len()
+        && grid[tile.0 as usize][tile.1 as usize].walkable
+}
+
+// Random sequential packing: eligible tiles are visited in random order and
+// kept when no pack is closer than min_distance, which gives a blue noise layout
+pub(crate) fn poisson_disc_packs(
+    candidates: &[(usize, usize)],
+    count: usize,
+    min_distance: f64,
+    rng: &mut ChaCha8Rng,
+) -> Vec<(usize, usize)> {
+    let mut candidates = candidates.to_vec();
+    candidates.shuffle(rng);
+    // buckets of packs, to only compare with the packs nearby
+    let cell_size = (min_distance / std::f64::consts::SQRT_2).max(1.0);
+    let cell = |tile: (usize, usize)| {
+        (
+            (tile.0 as f64 / cell_size) as i64,
+            (tile.1 as f64 / cell_size) as i64,
+        )
+    };
+    let reach = (min_distance / cell_size).ceil() as i64;
+    let mut cells: HashMap<(i64, i64), Vec<(usize, usize)>> = HashMap::new();
+    let mut packs = Vec::new();
+    for tile in candidates {
+        if packs.len() >= count {
+            break;
+        }
+        let (cx, cy) = cell(tile);
+        let too_close = (cx - reach..=cx + reach).any(|x| {
+            (cy - reach..=cy + reach).any(|y| {
+                cells.get(&(x, y)).into_iter().flatten().any(|other| {
+                    let dx = other.0 as f64 - tile.0 as f64;
+                    let dy = other.1 as f64 - tile.1 as f64;
+                    dx * dx + dy * dy < min_distance * min_distance
+                })
+            })
+        });
+        if !too_close {
+            cells.entry((cx, cy)).or_default().push(tile);
+            packs.push(tile);
+        }
+    }
+    packs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::init_grid;
+    use crate::area_gen::maps::{define_floor_patterns, TileType};
+    use crate::area_gen::pathfinding::{distance_map, PathOptions};
+    use crate::area_gen::test_helpers::generate;
+    use rand::SeedableRng;
+
+    fn table(packs: Vec<PackComposition>, modifier_count: (usize, usize)) -> MonsterTable {
+        let roll = RarityRoll {
+            chance: 1.0,
+            modifier_count,
+        };
+        MonsterTable {
+            packs,
+            rare: roll,
+            unique: roll,
+            modifiers: vec![MonsterModifier::Fast, MonsterModifier::ExtraHealth],
+            jitter: 0.0,
+        }
+    }
+
+    fn composition(
+        weight: u32,
+        leaders: &[&str],
+        escort: Vec<MonsterEntry>,
+        size: (usize, usize),
+    ) -> PackComposition {
+        PackComposition {
+            weight,
+            leaders: leaders.iter().map(|leader| String::from(*leader)).collect(),
+            escort,
+            size,
+            formation: Formation::Cluster,
+        }
+    }
+
+    fn mobs(table: &MonsterTable) -> Vec<Enemy> {
+        let mut grid = init_grid(5, 5, TileType::Floor);
+        for column in grid.iter_mut() {
+            for tile in column.iter_mut() {
+                tile.walkable = true;
+            }
+        }
+        let gradient = DifficultyGradient {
+            density: Curve::FLAT,
+            pack_size: Curve::FLAT,
+            rare_chance: Curve::FLAT,
+        };
+        let packs = [MobPack {
+            tile_coords: (3, 3),
+            difficulty: 0.5,
+        }];
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        generate_mobs(&grid, &packs, table, &gradient, &mut rng, 60, 20)
+    }
+
+    #[test]
+    fn tables_that_can_not_roll_skip_the_pack() {
+        let escort = vec![MonsterEntry::new("goblin", 1)];
+        for packs in [
+            vec![],
+            vec![composition(0, &[], escort.clone(), (2, 3))],
+            vec![composition(1, &[], escort.clone(), (3, 2))],
+            vec![composition(1, &[], vec![], (2, 3))],
+            vec![composition(
+                1,
+                &[],
+                vec![MonsterEntry::new("goblin", 0)],
+                (2, 3),
+            )],
+        ] {
+            assert!(mobs(&table(packs, (0, 1))).is_empty());
+        }
+    }
+
+    #[test]
+    fn zero_weight_escorts_leave_the_leaders_alone() {
+        let packs = vec![composition(
+            1,
+            &["shaman"],
+            vec![MonsterEntry::new("goblin", 0)],
+            (2, 3),
+        )];
+        let mobs = mobs(&table(packs, (0, 1)));
+        assert_eq!(mobs.len(), 1);
+        assert_eq!(mobs[0].monster_id, "shaman");
+    }
+
+    #[test]
+    fn upside_down_modifier_counts_give_no_modifiers() {
+        let packs = vec![composition(1, &["shaman"], vec![], (1, 1))];
+        let mobs = mobs(&table(packs, (2, 1)));
+        assert_eq!(mobs[0].rarity, Rarity::Unique);
+        assert!(mobs[0].modifiers.is_empty());
+    }
+
+    #[test]
+    fn packs_stay_out_of_the_safe_radius() {
+        for (map_index, map) in define_floor_patterns().iter().enumerate() {
+            for seed in 1..=3 {
+                let area = generate(map_index, seed);
+                let spawn = px_to_tile(
+                    (
+                        area.player_spawn_position.0 as f32,
+                        area.player_spawn_position.1 as f32,
+                    ),
+                    area.tile_size,
+                );
+                let distances = distance_map(
+                    &area,
+                    (spawn.0 as usize, spawn.1 as usize),
+                    &PathOptions::default(),
+                );
+                for pack in &area.packs {
+                    let distance = distances
+                        [pack.tile.0 as usize * area.height as usize + pack.tile.1 as usize];
+                    assert!(
+                        distance.is_finite() && distance as f64 >= map.spawn_safe_radius,
+                        "{} seed {} : pack at {}",
+                        map.name,
+                        seed,
+                        distance
+                    );
+                }
+            }
+        }
+    }
+
+    fn assert_spaced(packs: &[(u32, u32)], min_distance: f64) {
+        for (index, pack) in packs.iter().enumerate() {
+            for other in &packs[index + 1..] {
+                let dx = pack.0 as f64 - other.0 as f64;
+                let dy = pack.1 as f64 - other.1 as f64;
+                assert!(dx.hypot(dy) >= min_distance, "{:?} and {:?}", pack, other);
+            }
+        }
+    }
+
+    #[test]
+    fn poisson_disc_packs_are_min_distance_apart() {
+        let candidates: Vec<(usize, usize)> =
+            (0..60).flat_map(|x| (0..60).map(move |y| (x, y))).collect();
+        for min_distance in [1.0, 2.5, 4.0, 8.0] {
+            let mut rng = ChaCha8Rng::seed_from_u64(min_distance as u64);
+            let packs = poisson_disc_packs(&candidates, candidates.len(), min_distance, &mut rng);
+            assert!(packs.len() > 1);
+            let packs: Vec<(u32, u32)> =
+                packs.iter().map(|(x, y)| (*x as u32, *y as u32)).collect();
+            assert_spaced(&packs, min_distance);
+        }
+        for (map_index, map) in define_floor_patterns().iter().enumerate() {
+            let PackDistribution::PoissonDisc { min_distance } = map.pack_distribution else {
+                continue;
+            };
+            for seed in 1..=3 {
+                let packs: Vec<(u32, u32)> = generate(map_index, seed)
+                    .packs
+                    .iter()
+                    .map(|pack| pack.tile)
+                    .collect();
+                assert_spaced(&packs, min_distance);
+            }
+        }
+    }
+
+    const FORMATIONS: [Formation; 4] = [
+        Formation::Cluster,
+        Formation::Ring,
+        Formation::Line,
+        Formation::Scattered,
+    ];
+
+    #[test]
+    fn formations_fit_their_radius_without_overlaps() {
+        for formation in FORMATIONS {
+            for count in 1..=12 {
+                let mut rng = ChaCha8Rng::seed_from_u64(count as u64);
+                let offsets = formation_offsets(formation, count, 20.0, &mut rng);
+                assert_eq!(offsets.len(), count);
+                let radius = formation_radius(formation, count, 20.0);
+                for (index, offset) in offsets.iter().enumerate() {
+                    assert!(offset.0.hypot(offset.1) <= radius + 1e-3);
+                    for other in &offsets[index + 1..] {
+                        assert!((offset.0 - other.0).hypot(offset.1 - other.1) > 1.0);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn packs_keep_their_rolled_size_and_stand_on_floor() {
+        // a corridor one tile wide along the row of the pack
+        let mut corridor = init_grid(7, 7, TileType::Wall);
+        for column in corridor.iter_mut() {
+            column[3].walkable = true;
+            column[3].tile_type = TileType::Floor;
+        }
+        let gradient = DifficultyGradient {
+            density: Curve::FLAT,
+            pack_size: Curve::FLAT,
+            rare_chance: Curve::FLAT,
+        };
+        let packs = [MobPack {
+            tile_coords: (3, 3),
+            difficulty: 0.5,
+        }];
+        let center = tile_center((3, 3), 60);
+        for formation in FORMATIONS {
+            for size in 1..=10 {
+                let mut pack = composition(
+                    1,
+                    &["shaman"],
+                    vec![MonsterEntry::new("goblin", 1)],
+                    (size, size),
+                );
+                pack.formation = formation;
+                let mut rng = ChaCha8Rng::seed_from_u64(size as u64);
+                let mobs = generate_mobs(
+                    &corridor,
+                    &packs,
+                    &table(vec![pack], (0, 0)),
+                    &gradient,
+                    &mut rng,
+                    60,
+                    20,
+                );
+                assert_eq!(mobs.len(), size);
+                let radius = formation_radius(formation, size, 20.0);
+                for mob in &mobs {
+                    let point = (mob.point.0 as f32, mob.point.1 as f32);
+                    assert!(is_walkable_px(&corridor, point, 60));
+                    // one more pixel for the rounding to whole pixels
+                    assert!((point.0 - center.0).hypot(point.1 - center.1) <= radius + 1.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn far_packs_are_larger_or_rarer() {
+        for (map_index, map) in define_floor_patterns().iter().enumerate() {
+            // (difficulty, mobs, led by a rare or unique) of every pack, over a few seeds
+            let mut packs = Vec::new();
+            for seed in 1..=4 {
+                let area = generate(map_index, seed);
+                for (index, pack) in area.packs.iter().enumerate() {
+                    let members: Vec<&Enemy> = area
+                        .enemies
+                        .iter()
+                        .filter(|mob| mob.pack == index)
+                        .collect();
+                    let rare = members.iter().any(|mob| mob.rarity != Rarity::Normal);
+                    packs.push((pack.difficulty, members.len(), rare));
+                }
+            }
+            packs.sort_by(|a, b| a.0.total_cmp(&b.0));
+            // first and last tenth of the packs along the way to the boss
+            let decile = packs.len() / 10;
+            let stats = |packs: &[(f32, usize, bool)]| {
+                let mobs = packs.iter().map(|pack| pack.1).sum::<usize>() as f64;
+                let rares = packs.iter().filter(|pack| pack.2).count() as f64;
+                (mobs / packs.len() as f64, rares / packs.len() as f64)
+            };
+            let near = stats(&packs[..decile]);
+            let far = stats(&packs[packs.len() - decile..]);
+            let curves = [map.difficulty.pack_size, map.difficulty.rare_chance];
+            assert!(curves.iter().any(|curve| curve.end > curve.start));
+            if curves[0].end > curves[0].start {
+                assert!(
+                    far.0 > near.0,
+                    "{}: {} mobs near, {} far",
+                    map.name,
+                    near.0,
+                    far.0
+                );
+            }
+            if curves[1].end > curves[1].start {
+                assert!(
+                    far.1 > near.1,
+                    "{}: {} rare near, {} far",
+                    map.name,
+                    near.1,
+                    far.1
+                );
+            }
+        }
+    }
+}
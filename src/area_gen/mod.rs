@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 // Custom
 use maps::{FloorPattern, Map, Tile, TileType};
 // RNG
@@ -6,50 +8,241 @@ use rand_chacha::ChaCha8Rng;
 // Image creation
 use image::ImageBuffer;
 
+use self::colliders::{BoxCollider, ColliderDecomposition};
+use self::connectors::{Connector, Gate};
+use self::contours::ContourExtraction;
+use self::landmarks::Landmark;
 use self::maps::MobPack;
+use self::mobs::{MonsterModifier, PackDistribution, Rarity};
+use self::navmesh::NavMesh;
+use self::pathfinding::PathOptions;
+use self::polygons::PolygonSimplification;
+use self::query::WalkableBitmap;
+use self::tiers::{AreaModifier, TierScaling};
 
-mod maps;
+pub mod acts;
+pub mod challenges;
+pub mod colliders;
+pub mod connectors;
+pub mod contours;
+pub mod corridors;
+pub mod dungeons;
+pub mod landmarks;
+pub mod maps;
+pub mod mobs;
+pub mod navmesh;
+pub mod pathfinding;
+pub mod polygons;
+pub mod query;
+pub mod search;
+pub mod seed_codes;
+pub mod serialization;
+pub mod smoothing;
+pub mod tiers;
 
 type Grid = Vec<Vec<Tile>>;
 
-const TILE_SIZE: i32 = 60;
-const MOB_SIZE: i32 = 20;
+const DEFAULT_TILE_SIZE: i32 = 60;
+const DEFAULT_MOB_SIZE: i32 = 20;
+// tiles along the edges of the generation grid the layout is never drawn on
+const LAYOUT_MARGIN: i32 = 8;
+// part of the seed codes, bumped when the same request stops giving the same area
+pub const GENERATOR_VERSION: u16 = 2;
+
+// What to generate, the same request always gives the same area
+#[derive(Clone)]
+pub struct GenerationRequest {
+    // index in define_floor_patterns
+    pub map_index: usize,
+    pub seed: u64,
+    // 0 and 1 are the base map, each tier above makes it bigger and harder
+    pub tier: u32,
+    pub modifiers: Vec<AreaModifier>,
+    // gates the area must have on its edges, to line up with the previous and next areas
+    pub entry: Option<Connector>,
+    pub exit: Option<Connector>,
+    // gates to optional side zones
+    pub side_gates: Vec<Connector>,
+    pub rerolls: Rerolls,
+}
+
+// Steps of the generation, each one rolls from its own stream of the seed so changing
+// the rolls of one stage does not reshuffle the other ones
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Stage {
+    Layout,
+    // exits, waypoints and checkpoints
+    Landmarks,
+    Packs,
+    Mobs,
+}
+
+// Number of times each stage was rolled again, all 0 for the area of the seed.
+// Rerolling a stage keeps the stages before it, the later ones follow the new result
+// (e.g. new packs keep the layout but get new mobs).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Rerolls {
+    pub layout: u32,
+    pub landmarks: u32,
+    pub packs: u32,
+    pub mobs: u32,
+}
+
+impl GenerationRequest {
+    pub fn random(map_index: usize) -> GenerationRequest {
+        GenerationRequest::new(map_index, rand::random())
+    }
+
+    // base tier, no modifiers, gates or rerolls
+    pub fn new(map_index: usize, seed: u64) -> GenerationRequest {
+        GenerationRequest {
+            map_index,
+            seed,
+            tier: 1,
+            modifiers: Vec::new(),
+            entry: None,
+            exit: None,
+            side_gates: Vec::new(),
+            rerolls: Rerolls::default(),
+        }
+    }
+
+    // The stream id is the stage in the low bits and its reroll count in the high ones
+    pub fn stage_rng(&self, stage: Stage) -> ChaCha8Rng {
+        let reroll = match stage {
+            Stage::Layout => self.rerolls.layout,
+            Stage::Landmarks => self.rerolls.landmarks,
+            Stage::Packs => self.rerolls.packs,
+            Stage::Mobs => self.rerolls.mobs,
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(((reroll as u64) << 32) | stage as u64);
+        rng
+    }
+}
+
+// How to build the outputs, does not change the generated layout
+#[derive(Clone)]
+pub struct GenerationConfig {
+    pub contour_extraction: ContourExtraction,
+    // only used by the tile staircase extraction
+    pub polygon_simplification: PolygonSimplification,
+    pub build_navmesh: bool,
+    pub colliders: ColliderDecomposition,
+    // save the map picture in the output folder
+    pub render_image: bool,
+    // print the seed and a summary of the area on stdout
+    pub print_summary: bool,
+    // size in pixels of a tile and of a mob, maps can override them
+    pub tile_size: i32,
+    pub mob_size: i32,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        GenerationConfig {
+            contour_extraction: ContourExtraction::TileStaircase,
+            polygon_simplification: PolygonSimplification::None,
+            build_navmesh: false,
+            colliders: ColliderDecomposition::None,
+            render_image: true,
+            print_summary: true,
+            tile_size: DEFAULT_TILE_SIZE,
+            mob_size: DEFAULT_MOB_SIZE,
+        }
+    }
+}
+
+impl GenerationConfig {
+    // no picture and nothing printed, e.g. to generate many areas
+    pub fn headless() -> GenerationConfig {
+        GenerationConfig {
+            render_image: false,
+            print_summary: false,
+            ..GenerationConfig::default()
+        }
+    }
+}
 
 pub struct AreaGenerationOutput {
+    pub metadata: AreaMetadata,
+    // pixel sizes used for the polygons, spawn and enemy points
+    pub tile_size: i32,
+    pub mob_size: i32,
     pub width: u32,
     pub height: u32,
     pub walkable_x: Vec<u32>,
     pub walkable_y: Vec<u32>,
-    pub oob_polygons: Vec<Shape>, // bool is true when outer oob shape, false when inner
+    // tile type and walkability of every tile, the index of tile (x, y) is x * height + y
+    pub tiles: Vec<TileType>,
+    pub walkable: WalkableBitmap,
+    pub oob_polygons: Vec<Shape>, // outer shape first, see Shape for holes and winding
     pub player_spawn_position: (i32, i32),
+    pub packs: Vec<Pack>,
     pub enemies: Vec<Enemy>, // pub ennemies: Vec<enemy>,
+    pub landmarks: Vec<Landmark>,
+    // one for each connector of the request, entry first and side gates last
+    pub gates: Vec<Gate>,
+    // number of tiles turned into floor to respect the map minimum corridor width
+    pub widened_tiles: usize,
+    // walking distance in tiles from the spawn to the closest pack, for QA
+    pub nearest_pack_distance: Option<f32>,
+    // only built when asked in the generation config
+    pub navmesh: Option<NavMesh>,
+    // empty unless a collider decomposition is asked in the generation config
+    pub colliders: Vec<BoxCollider>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AreaMetadata {
+    pub map_name: String,
+    pub seed: u64,
+    pub tier: u32,
+    pub modifiers: Vec<AreaModifier>,
+    pub entry: Option<Connector>,
+    pub exit: Option<Connector>,
+    pub side_gates: Vec<Connector>,
+    pub rerolls: Rerolls,
 }
 
+// Walkable area is inside shapes with an even depth (outer shape and islands),
+// and outside shapes with an odd depth (holes).
+// Points of walkable inside shapes are clockwise on screen (y pointing down),
+// points of holes are counter clockwise.
 pub struct Shape {
     pub points: Vec<(f32, f32)>,
+    // true for holes, the walkable area is outside of the shape
     pub inner_if_true: bool,
-}
-
-#[derive(Clone)]
-pub enum EnemyType {
-    Ranged,
-    Melee,
+    // index in oob_polygons of the smallest shape containing this one
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub depth: usize,
 }
 
 pub struct Enemy {
     pub point: (u32, u32),
-    pub mob_type: EnemyType,
+    // id from the monster table of the map
+    pub monster_id: String,
+    pub rarity: Rarity,
+    pub modifiers: Vec<MonsterModifier>,
+    // index in the packs of the output
+    pub pack: usize,
+}
+
+pub struct Pack {
+    pub tile: (u32, u32),
+    // progress from the spawn (0.0) to the boss (1.0), the gradient curves of the map are applied to it
+    pub difficulty: f32,
 }
 
-pub fn generate_area(map_index: usize) -> AreaGenerationOutput {
-    // Create random generator from seed
-    // fixed seed
-    // let seed: u64 = ;
-    // random seed
-    let seed: u64 = rand::random();
-    // The rng instance is created from the seed
-    let mut rng: ChaCha8Rng = ChaCha8Rng::seed_from_u64(seed);
-    println!("{}", seed);
+pub fn generate_area(
+    request: &GenerationRequest,
+    config: &GenerationConfig,
+) -> AreaGenerationOutput {
+    let seed: u64 = request.seed;
+    if config.print_summary {
+        println!("{}", seed);
+    }
 
     let mut maps = maps::define_floor_patterns();
     //------------------------------------------------------//
@@ -57,16 +250,57 @@ pub fn generate_area(map_index: usize) -> AreaGenerationOutput {
     //------------------------------------------------------//
 
     // Pick a map
-    let map = maps.remove(map_index);
+    let mut map = maps.remove(request.map_index);
+    TierScaling::new(request.tier, &request.modifiers).apply(&mut map);
     let map_name = map.name.clone();
+    let tile_size = map.tile_size.unwrap_or(config.tile_size);
+    let mob_size = map.mob_size.unwrap_or(config.mob_size);
+    let monsters = map.monsters.clone();
+    let difficulty = map.difficulty;
     // Generate map grid
-    let (mut grid, player_spawn_position, packs) = generate_map(&mut rng, map);
+    let GeneratedMap {
+        mut grid,
+        player_spawn_position,
+        packs,
+        widened_tiles,
+        nearest_pack_distance,
+        landmarks,
+    } = generate_map(map, tile_size, request);
+    let gates = connectors::gates(
+        request.entry,
+        request.exit,
+        &request.side_gates,
+        grid.len(),
+        grid[0].len(),
+        tile_size,
+    );
 
     //------------------------------------------------------//
     //               Find oob polygons                      //
     //------------------------------------------------------//
-    let oob_polygons = find_oob_polygons(&mut grid);
-    render_grid(&grid, map_name.clone(), false);
+    let mut oob_polygons = match config.contour_extraction {
+        ContourExtraction::TileStaircase => find_oob_polygons(&mut grid, tile_size),
+        ContourExtraction::MarchingSquares {
+            smoothing,
+            tolerance,
+        } => contours::extract_contours(&grid, smoothing, tolerance, tile_size),
+    };
+    polygons::build_hierarchy(&mut oob_polygons);
+    if let ContourExtraction::TileStaircase = config.contour_extraction {
+        polygons::simplify_shapes(&mut oob_polygons, config.polygon_simplification, tile_size);
+    }
+    // every extraction and simplification must keep the hierarchy and winding guarantees
+    debug_assert_eq!(polygons::validate_shapes(&oob_polygons), Ok(()));
+    if config.render_image {
+        render_grid(&grid, map_name.clone(), false);
+    }
+
+    let navmesh = if config.build_navmesh {
+        Some(NavMesh::from_grid(&grid, tile_size))
+    } else {
+        None
+    };
+    let colliders = colliders::build_colliders(&grid, config.colliders, tile_size);
 
     // render_grid(&grid, map_name.clone() + "_outline", true);
 
@@ -74,91 +308,93 @@ pub fn generate_area(map_index: usize) -> AreaGenerationOutput {
     //               Generate mobs                          //
     //------------------------------------------------------//
 
-    let enemies = generate_mobs(&packs, &mut rng);
+    let enemies = mobs::generate_mobs(
+        &grid,
+        &packs,
+        &monsters,
+        &difficulty,
+        &mut request.stage_rng(Stage::Mobs),
+        tile_size,
+        mob_size,
+    );
+    let pack_outputs = packs
+        .iter()
+        .map(|pack| Pack {
+            tile: (pack.tile_coords.0 as u32, pack.tile_coords.1 as u32),
+            difficulty: pack.difficulty as f32,
+        })
+        .collect();
 
     // Initiate module outputf
     let mut walkable_x = Vec::new();
     let mut walkable_y = Vec::new();
-    for x in 0..grid.len() {
-        for y in 0..grid[0].len() {
-            if grid[x][y].walkable {
+    let mut tiles = Vec::new();
+    let mut walkable = WalkableBitmap::new(grid.len(), grid[0].len());
+    for (x, column) in grid.iter().enumerate() {
+        for (y, tile) in column.iter().enumerate() {
+            tiles.push(tile.tile_type);
+            walkable.set(x, y, tile.walkable);
+            if tile.walkable {
                 walkable_x.push(x as u32);
                 walkable_y.push(y as u32);
             }
         }
     }
-    println!(
-        "----------------------------\nSeed : {} \n    Biome : {}\n    Size  : {} x {} tiles\n    Packs : {} \n    Monsters : {}",
-        seed,
-        map_name,
-        grid.len(),
-        grid[0].len(),
-        packs.len(),
-        enemies.len(),
-    );
+    if config.print_summary {
+        println!(
+            "----------------------------\nSeed : {} \n    Code  : {}\n    Biome : {}\n    Tier  : {}\n    Size  : {} x {} tiles\n    Packs : {} \n    Monsters : {}\n    Widened : {} tiles\n    Nearest pack : {:.1} tiles",
+            seed,
+            seed_codes::encode_seed_code(request),
+            map_name,
+            request.tier,
+            grid.len(),
+            grid[0].len(),
+            packs.len(),
+            enemies.len(),
+            widened_tiles,
+            nearest_pack_distance.unwrap_or(f32::INFINITY),
+        );
+    }
     AreaGenerationOutput {
+        metadata: AreaMetadata {
+            map_name,
+            seed,
+            tier: request.tier,
+            modifiers: request.modifiers.clone(),
+            entry: request.entry,
+            exit: request.exit,
+            side_gates: request.side_gates.clone(),
+            rerolls: request.rerolls,
+        },
+        tile_size,
+        mob_size,
         oob_polygons,
         width: grid.len() as u32,
         height: grid[0].len() as u32,
         walkable_x,
         walkable_y,
+        tiles,
+        walkable,
         player_spawn_position,
+        packs: pack_outputs,
         enemies,
+        landmarks,
+        gates,
+        widened_tiles,
+        nearest_pack_distance,
+        navmesh,
+        colliders,
     }
 }
 
-fn generate_mobs(packs: &Vec<MobPack>, rng: &mut ChaCha8Rng) -> Vec<Enemy> {
-    let mut mobs = Vec::new();
-    let max_mobs_per_pack = (TILE_SIZE / MOB_SIZE) * (TILE_SIZE / MOB_SIZE);
-    let tile_size = TILE_SIZE as usize;
-    let mob_size = MOB_SIZE as usize;
-    for pack in packs {
-        let mut x_offset = 0;
-        let mut y_offset = 0;
-        let nb_monsters = rng.gen_range(0..max_mobs_per_pack);
-        for _ in 0..=nb_monsters {
-            if (pack.tile_coords.0 * tile_size) + x_offset + mob_size
-                <= (pack.tile_coords.0 + 1) * tile_size
-            {
-                x_offset += mob_size;
-            } else {
-                x_offset = 0;
-                y_offset += mob_size;
-            }
-            let mob_coord_x = (pack.tile_coords.0 * tile_size) + x_offset + (mob_size / 2);
-            let mob_coord_y = (pack.tile_coords.1 * tile_size) + y_offset + (mob_size / 2);
-
-            let mob_type = match rng.gen_range(0u8..=1u8) {
-                0 => EnemyType::Melee,
-                1 => EnemyType::Ranged,
-                _ => EnemyType::Melee,
-            };
-            mobs.push(Enemy {
-                mob_type: mob_type.clone(),
-                point: (mob_coord_x as u32, mob_coord_y as u32),
-            });
-            // debug monster position
-            // println!(
-            //     "tile:{} {}, {} monsters, mob_coords: {} {}, type: {}",
-            //     pack.tile_coords.0,
-            //     pack.tile_coords.1,
-            //     nb_monsters + 1,
-            //     mob_coord_x,
-            //     mob_coord_y,
-            //     match mob_type {
-            //         EnemyType::Melee => "Melee",
-            //         EnemyType::Ranged => "Ranged",
-            //     }
-            // );
-        }
-    }
-
-    mobs
-}
-
-fn add_mob_packs(grid: &mut Grid, rng: &mut ChaCha8Rng, density: f64) -> Vec<MobPack> {
+fn add_mob_packs(
+    grid: &mut Grid,
+    rng: &mut ChaCha8Rng,
+    map: &Map,
+    spawn_distances: &[f32],
+    progress: &[f64],
+) -> Vec<MobPack> {
     let mut nb_walkable = 0;
-    let mut packs = Vec::new();
     for row in grid.iter_mut() {
         for tile in row {
             if tile.walkable {
@@ -166,38 +402,74 @@ fn add_mob_packs(grid: &mut Grid, rng: &mut ChaCha8Rng, density: f64) -> Vec<Mob
             }
         }
     }
-    let nb_packs = (density * nb_walkable as f64) as i32;
-    let tiles_iter = nb_walkable / nb_packs;
-    // Pas utiliser directement tiles_iter, mais le randomisser de 0 a tile_itter
-    let mut iter = 0;
-    let mut next_iter = tiles_iter;
-    let grid_copy = grid.to_vec();
-    for (x, row) in grid.iter_mut().enumerate() {
-        for (y, tile) in row.iter_mut().enumerate() {
-            if tile.walkable
-                && iter >= next_iter
-                && tile.spawnable
-                // No monsters next to walls
-                && grid_copy[x+1][y].walkable
-                && grid_copy[x-1][y].walkable
-                && grid_copy[x][y+1].walkable
-                && grid_copy[x][y-1].walkable
-            {
-                tile.mob_pack = Some(maps::MobPack {
-                    tile_coords: (x, y),
-                });
-                packs.push(maps::MobPack {
-                    tile_coords: (x, y),
-                });
-
-                iter = 0;
-                next_iter = rng.gen_range((tiles_iter as f64 * 0.7) as i32..tiles_iter);
+    let height = grid[0].len();
+    let can_spawn = |grid: &Grid, x: usize, y: usize| {
+        grid[x][y].walkable
+            && grid[x][y].spawnable
+            // No monsters next to walls
+            && grid[x + 1][y].walkable
+            && grid[x - 1][y].walkable
+            && grid[x][y + 1].walkable
+            && grid[x][y - 1].walkable
+            // unreachable tiles have an infinite distance
+            && spawn_distances[x * height + y] as f64 >= map.spawn_safe_radius
+    };
+    // placed for the densest part of the map, then thinned out along the gradient
+    let max_density = map.density * map.difficulty.density.max();
+    // at most one pack per walkable tile, whatever the density modifiers
+    let nb_packs = (max_density * nb_walkable as f64).min(nb_walkable as f64) as i32;
+    // e.g. towns
+    if nb_packs <= 0 {
+        return Vec::new();
+    }
+    let mut pack_tiles = Vec::new();
+    match map.pack_distribution {
+        PackDistribution::ScanOrder => {
+            let tiles_iter = nb_walkable / nb_packs;
+            // Pas utiliser directement tiles_iter, mais le randomisser de 0 a tile_itter
+            let mut iter = 0;
+            let mut next_iter = tiles_iter;
+            for x in 0..grid.len() {
+                for y in 0..grid[0].len() {
+                    if iter >= next_iter && can_spawn(grid, x, y) {
+                        pack_tiles.push((x, y));
+                        iter = 0;
+                        next_iter = rng.gen_range((tiles_iter as f64 * 0.7) as i32..tiles_iter);
+                    }
+                    if grid[x][y].walkable {
+                        iter += 1;
+                    }
+                }
             }
-            if tile.walkable {
-                iter += 1;
+        }
+        PackDistribution::PoissonDisc { min_distance } => {
+            let mut candidates = Vec::new();
+            for x in 1..grid.len() - 1 {
+                for y in 1..grid[0].len() - 1 {
+                    if can_spawn(grid, x, y) {
+                        candidates.push((x, y));
+                    }
+                }
             }
+            pack_tiles =
+                mobs::poisson_disc_packs(&candidates, nb_packs as usize, min_distance, rng);
         }
     }
+
+    let mut packs = Vec::new();
+    for (x, y) in pack_tiles {
+        let difficulty = progress[x * height + y];
+        let keep_chance = map.difficulty.density.at(difficulty) / map.difficulty.density.max();
+        if !rng.gen_bool(keep_chance.clamp(0.0, 1.0)) {
+            continue;
+        }
+        let pack = maps::MobPack {
+            tile_coords: (x, y),
+            difficulty,
+        };
+        grid[x][y].mob_pack = Some(pack.clone());
+        packs.push(pack);
+    }
     // reset scanned tracker
     for row in grid.iter_mut() {
         for tile in row {
@@ -207,7 +479,7 @@ fn add_mob_packs(grid: &mut Grid, rng: &mut ChaCha8Rng, density: f64) -> Vec<Mob
     packs
 }
 
-fn find_oob_polygons(grid: &mut Grid) -> Vec<Shape> {
+fn find_oob_polygons(grid: &mut Grid, tile_size: i32) -> Vec<Shape> {
     // Find a first point on the map contour
     let mut oob_polygons = Vec::new();
     let mut current_pos = (0, (grid[0].len() / 2) as i32);
@@ -218,8 +490,11 @@ fn find_oob_polygons(grid: &mut Grid) -> Vec<Shape> {
     current_pos.0 -= 1;
     // Generate polygone of the outside of the map
     oob_polygons.push(Shape {
-        points: find_oob_polygone(current_pos, grid, (0, 1)),
+        points: find_oob_polygone(current_pos, grid, (0, 1), tile_size),
         inner_if_true: false,
+        parent: None,
+        children: Vec::new(),
+        depth: 0,
     });
     // find inside map polygones
     // scan the grid and search for tiles that are not floor but next to floor, and not already scanned
@@ -234,8 +509,11 @@ fn find_oob_polygons(grid: &mut Grid) -> Vec<Shape> {
                         || grid[x][y - 1].walkable)
                 {
                     oob_polygons.push(Shape {
-                        points: find_oob_polygone((x as i32, y as i32), grid, (0, -1)),
+                        points: find_oob_polygone((x as i32, y as i32), grid, (0, -1), tile_size),
                         inner_if_true: true,
+                        parent: None,
+                        children: Vec::new(),
+                        depth: 1,
                     });
                     continue 'outer;
                 }
@@ -251,6 +529,7 @@ fn find_oob_polygone(
     start_point: (i32, i32),
     grid: &mut Grid,
     start_dir: (i32, i32),
+    tile_size: i32,
 ) -> Vec<(f32, f32)> {
     let mut tile_polygone = Vec::new();
     let mut px_polygone: Vec<(f32, f32)> = Vec::new();
@@ -258,9 +537,16 @@ fn find_oob_polygone(
 
     let mut dir = start_dir;
     let mut next_dir = dir;
-    let mut first_polygone_first_point = (0, 0);
-    // continue tracing until we come back where to the first corner
-    while current_pos != first_polygone_first_point {
+    // number of corners found before each tile and direction of the trace, the trace can
+    // go through the same tile twice in different directions
+    let mut visited = HashMap::new();
+    // continue tracing until we come back to a tile in the same direction,
+    // the corners found since then are the polygon
+    loop {
+        if let Some(start) = visited.insert((current_pos, dir), px_polygone.len()) {
+            px_polygone.drain(..start);
+            break;
+        }
         // if current dir is down
         if dir == (0, 1) {
             // right is floor
@@ -271,8 +557,8 @@ fn find_oob_polygone(
                     tile_polygone.push(current_pos);
                     //keep bottom right point
                     px_polygone.push((
-                        (current_pos.0 * TILE_SIZE) as f32,
-                        (current_pos.1 * TILE_SIZE) as f32,
+                        (current_pos.0 * tile_size) as f32,
+                        (current_pos.1 * tile_size) as f32,
                     ));
                     next_dir = (-1, 0);
                 } else {
@@ -283,8 +569,8 @@ fn find_oob_polygone(
                 tile_polygone.push(current_pos);
                 // keep up right point
                 px_polygone.push((
-                    (current_pos.0 * TILE_SIZE) as f32,
-                    ((current_pos.1 * TILE_SIZE) - TILE_SIZE) as f32,
+                    (current_pos.0 * tile_size) as f32,
+                    ((current_pos.1 * tile_size) - tile_size) as f32,
                 ));
                 next_dir = (1, 0);
             }
@@ -299,8 +585,8 @@ fn find_oob_polygone(
                     tile_polygone.push(current_pos);
                     //keep bottom left
                     px_polygone.push((
-                        ((current_pos.0 * TILE_SIZE) - TILE_SIZE) as f32,
-                        (current_pos.1 * TILE_SIZE) as f32,
+                        ((current_pos.0 * tile_size) - tile_size) as f32,
+                        (current_pos.1 * tile_size) as f32,
                     ));
                     next_dir = (0, -1);
                 } else {
@@ -310,8 +596,8 @@ fn find_oob_polygone(
                 tile_polygone.push(current_pos);
                 //keep bottom right
                 px_polygone.push((
-                    (current_pos.0 * TILE_SIZE) as f32,
-                    (current_pos.1 * TILE_SIZE) as f32,
+                    (current_pos.0 * tile_size) as f32,
+                    (current_pos.1 * tile_size) as f32,
                 ));
                 next_dir = (0, 1);
             }
@@ -326,8 +612,8 @@ fn find_oob_polygone(
                     tile_polygone.push(current_pos);
                     // keep up right point
                     px_polygone.push((
-                        (current_pos.0 * TILE_SIZE) as f32,
-                        ((current_pos.1 * TILE_SIZE) - TILE_SIZE) as f32,
+                        (current_pos.0 * tile_size) as f32,
+                        ((current_pos.1 * tile_size) - tile_size) as f32,
                     ));
                     next_dir = (0, 1);
                 } else {
@@ -337,8 +623,8 @@ fn find_oob_polygone(
                 tile_polygone.push(current_pos);
                 // keep up left point
                 px_polygone.push((
-                    ((current_pos.0 * TILE_SIZE) - TILE_SIZE) as f32,
-                    ((current_pos.1 * TILE_SIZE) - TILE_SIZE) as f32,
+                    ((current_pos.0 * tile_size) - tile_size) as f32,
+                    ((current_pos.1 * tile_size) - tile_size) as f32,
                 ));
                 next_dir = (0, -1);
             }
@@ -353,8 +639,8 @@ fn find_oob_polygone(
                     tile_polygone.push(current_pos);
                     // keep up left point
                     px_polygone.push((
-                        ((current_pos.0 * TILE_SIZE) - TILE_SIZE) as f32,
-                        ((current_pos.1 * TILE_SIZE) - TILE_SIZE) as f32,
+                        ((current_pos.0 * tile_size) - tile_size) as f32,
+                        ((current_pos.1 * tile_size) - tile_size) as f32,
                     ));
                     next_dir = (1, 0);
                 } else {
@@ -365,28 +651,39 @@ fn find_oob_polygone(
                 tile_polygone.push(current_pos);
                 // keep bottom left point
                 px_polygone.push((
-                    ((current_pos.0 * TILE_SIZE) - TILE_SIZE) as f32,
-                    (current_pos.1 * TILE_SIZE) as f32,
+                    ((current_pos.0 * tile_size) - tile_size) as f32,
+                    (current_pos.1 * tile_size) as f32,
                 ));
                 next_dir = (-1, 0);
             }
         }
         dir = next_dir;
-        // store break condition
-        if tile_polygone.len() == 1 {
-            first_polygone_first_point = current_pos;
-        }
         // flag current point to avoid scanning this polygon again later
         grid[current_pos.0 as usize][current_pos.1 as usize].scanned = true;
         // move to next point
         current_pos.0 += dir.0;
         current_pos.1 += dir.1;
     }
+    // two tiles next to each other on the trace can give the same corner
+    px_polygone.dedup();
+    if px_polygone.len() > 1 && px_polygone.first() == px_polygone.last() {
+        px_polygone.pop();
+    }
 
     px_polygone
 }
 
-fn generate_map(rng: &mut ChaCha8Rng, map: Map) -> (Grid, (i32, i32), Vec<MobPack>) {
+struct GeneratedMap {
+    grid: Grid,
+    player_spawn_position: (i32, i32),
+    packs: Vec<MobPack>,
+    widened_tiles: usize,
+    nearest_pack_distance: Option<f32>,
+    landmarks: Vec<Landmark>,
+}
+
+fn generate_map(map: Map, tile_size: i32, request: &GenerationRequest) -> GeneratedMap {
+    let rng = &mut request.stage_rng(Stage::Layout);
     let oob_tiletype = map.oob_type;
 
     let grid_size = 1500;
@@ -396,7 +693,7 @@ fn generate_map(rng: &mut ChaCha8Rng, map: Map) -> (Grid, (i32, i32), Vec<MobPac
 
     // genrate walkable paths based on a random selection of possible biomes
     let mut center = (grid_size / 2, grid_size / 2);
-    let map_start = center;
+    let mut map_start = center;
     // Add
     for i in 0..map.biomes.len() {
         center = generate_walkable_layout(&mut grid, &map.biomes[i], rng, center);
@@ -407,6 +704,19 @@ fn generate_map(rng: &mut ChaCha8Rng, map: Map) -> (Grid, (i32, i32), Vec<MobPac
     remove_small_cluster(&mut grid, oob_tiletype, 4, true, false);
     remove_small_cluster(&mut grid, oob_tiletype, 4, false, true);
 
+    // round corners and widen passages with the map smoothing steps
+    if !map.smoothing.is_empty() {
+        for step in &map.smoothing {
+            smoothing::apply_step(&mut grid, oob_tiletype, step);
+        }
+        // erosion can cut the area in pieces, or remove the first and last centers
+        map_start = nearest_walkable_tile(&grid, map_start);
+        smoothing::remove_unreachable_floor(&mut grid, oob_tiletype, map_start);
+        center = nearest_walkable_tile(&grid, center);
+    }
+    // done last so no smoothing step can narrow a passage again
+    let widened_tiles = corridors::enforce_min_corridor_width(&mut grid, map.min_corridor_width);
+
     // add Start of map, first center and last center
     draw_rectangle(&mut grid, TileType::Start, (5, 5), map_start, true, false);
     draw_rectangle(&mut grid, TileType::Boss, (1, 1), center, true, true);
@@ -414,87 +724,135 @@ fn generate_map(rng: &mut ChaCha8Rng, map: Map) -> (Grid, (i32, i32), Vec<MobPac
     // resize_grid to it's minimum size
     resize_grid(&mut grid, 4);
 
+    // open the gates asked by the request, the spawn moves next to the entry
+    if let Some(entry) = request.entry {
+        let spawn = connectors::carve_gate(&mut grid, entry);
+        move_start(&mut grid, spawn);
+    }
+    let exit_tile = request
+        .exit
+        .map(|exit| connectors::carve_gate(&mut grid, exit));
+    for side_gate in &request.side_gates {
+        connectors::carve_gate(&mut grid, *side_gate);
+    }
+
     let mut start_after_resize = (0, 0);
-    'outer: for x in 0..grid.len() {
-        for y in 0..grid[0].len() {
-            if grid[x][y].tile_type == TileType::Start {
+    'outer: for (x, column) in grid.iter().enumerate() {
+        for (y, tile) in column.iter().enumerate() {
+            if tile.tile_type == TileType::Start {
                 start_after_resize = (x as i32, y as i32);
                 break 'outer;
             }
         }
     }
+    let mut boss_after_resize = None;
+    for (x, column) in grid.iter().enumerate() {
+        for (y, tile) in column.iter().enumerate() {
+            if tile.tile_type == TileType::Boss {
+                boss_after_resize = Some((x, y));
+            }
+        }
+    }
+
+    // walking distances from the spawn, with the default movement rules
+    let spawn_distances = pathfinding::distance_map(
+        &(&grid, tile_size),
+        (start_after_resize.0 as usize, start_after_resize.1 as usize),
+        &PathOptions::default(),
+    );
 
     // add events on map, tag them as non spawnable
-    // TODO
+    let critical_path = boss_after_resize.and_then(|boss| {
+        pathfinding::find_path(
+            &(&grid, tile_size),
+            (start_after_resize.0 as usize, start_after_resize.1 as usize),
+            boss,
+            &PathOptions::default(),
+        )
+    });
+    let landmarks = landmarks::place_landmarks(
+        &mut grid,
+        &map.landmarks,
+        critical_path.as_ref(),
+        exit_tile,
+        &spawn_distances,
+        &mut request.stage_rng(Stage::Landmarks),
+        tile_size,
+    );
 
     // add mob packs
-    let mob_packs = add_mob_packs(&mut grid, rng, map.density);
+    let boss_distances = match boss_after_resize {
+        Some(boss) => pathfinding::distance_map(&(&grid, tile_size), boss, &PathOptions::default()),
+        None => vec![f32::INFINITY; spawn_distances.len()],
+    };
+    // how far along the way from the spawn to the boss each tile is
+    let progress: Vec<f64> = spawn_distances
+        .iter()
+        .zip(&boss_distances)
+        .map(|(from_spawn, to_boss)| {
+            let total = from_spawn + to_boss;
+            if total.is_finite() && total > 0.0 {
+                (from_spawn / total) as f64
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    let mob_packs = add_mob_packs(
+        &mut grid,
+        &mut request.stage_rng(Stage::Packs),
+        &map,
+        &spawn_distances,
+        &progress,
+    );
+    let height = grid[0].len();
+    let nearest_pack_distance = mob_packs
+        .iter()
+        .map(|pack| spawn_distances[pack.tile_coords.0 * height + pack.tile_coords.1])
+        .min_by(f32::total_cmp);
 
     // // print grid
     // render_grid(&grid, map.name.clone());
-    (
+    GeneratedMap {
         grid,
-        (
-            (start_after_resize.0 * TILE_SIZE) - (TILE_SIZE / 2),
-            (start_after_resize.1 * TILE_SIZE) - (TILE_SIZE / 2),
+        player_spawn_position: (
+            (start_after_resize.0 * tile_size) - (tile_size / 2),
+            (start_after_resize.1 * tile_size) - (tile_size / 2),
         ),
-        mob_packs,
-    )
+        packs: mob_packs,
+        widened_tiles,
+        nearest_pack_distance,
+        landmarks,
+    }
 }
 
 fn resize_grid(grid: &mut Grid, border_size: usize) {
     // for each direction
     // left to right
-    let height = grid[0].len();
-    'outer: loop {
-        for y in 0..height {
-            if grid[border_size][y].walkable {
-                break 'outer;
-            }
-        }
+    while !grid[border_size].iter().any(|tile| tile.walkable) {
         grid.remove(0);
     }
     //right to left
     let mut x = grid.len() - 1;
-
-    'outer: loop {
-        for y in 0..height {
-            if grid[x - border_size][y].walkable {
-                break 'outer;
-            }
-        }
+    while !grid[x - border_size].iter().any(|tile| tile.walkable) {
         x -= 1;
     }
     grid.truncate(x);
     // bottom to up
     let mut y = 0;
-    let width = grid.len();
-    'outer: loop {
-        for x in 0..width {
-            if grid[x][y + border_size].walkable {
-                break 'outer;
-            }
-        }
+    while !grid.iter().any(|column| column[y + border_size].walkable) {
         y += 1;
     }
-    for x in 0..width {
-        for _ in 0..y {
-            grid[x].remove(0);
-        }
+    for column in grid.iter_mut() {
+        column.drain(..y);
     }
     // Top to bottom
     y = grid[0].len() - 1;
-
-    'outer: loop {
-        for x in 0..width {
-            if grid[x][y - border_size].walkable {
-                break 'outer;
-            }
-        }
+    while !grid.iter().any(|column| column[y - border_size].walkable) {
         y -= 1;
     }
-    for x in 0..width {
-        grid[x].truncate(y);
+    for column in grid.iter_mut() {
+        column.truncate(y);
     }
 }
 
@@ -678,6 +1036,32 @@ fn find_point_on_edge(
     current_position
 }
 
+// tile x covers the pixels from (x - 1) * tile_size to x * tile_size
+fn tile_center(tile: (usize, usize), tile_size: i32) -> (f32, f32) {
+    (
+        (tile.0 as i32 * tile_size - tile_size / 2) as f32,
+        (tile.1 as i32 * tile_size - tile_size / 2) as f32,
+    )
+}
+
+fn px_to_tile(point: (f32, f32), tile_size: i32) -> (i32, i32) {
+    (
+        (point.0 / tile_size as f32).floor() as i32 + 1,
+        (point.1 / tile_size as f32).floor() as i32 + 1,
+    )
+}
+
+// Closest walkable tile of the grid, the tile itself when the grid has none
+fn nearest_walkable_tile(grid: &Grid, from: (i32, i32)) -> (i32, i32) {
+    query::nearest_walkable_tile(
+        from,
+        (grid.len(), grid[0].len()),
+        |x, y| grid[x][y].walkable,
+        |x, y| (x as f32 - from.0 as f32).hypot(y as f32 - from.1 as f32),
+    )
+    .map_or(from, |(x, y)| (x as i32, y as i32))
+}
+
 // fn roll_direction(direction: &Vec<((i32, i32), i32)>, rng: &mut ChaCha8Rng) -> (i32, i32) {
 //     let total_probability = direction
 //         .iter()
@@ -705,16 +1089,51 @@ fn draw_rectangle(
     walkable: bool,
     spawnable: bool,
 ) {
+    // the layout never reaches the edge of the grid, the later steps read the neighbours of
+    // every floor tile
+    let inside = |coordinate: i32, length: usize| {
+        coordinate >= LAYOUT_MARGIN && coordinate < length as i32 - LAYOUT_MARGIN
+    };
     for x in 0..size.0 {
         for y in 0..size.1 {
-            add_tile(
-                grid,
-                ((center.0 - (size.0 / 2)) + x) as usize,
-                ((center.1 - (size.1 / 2)) + y) as usize,
-                tiletype,
-                walkable,
-                spawnable,
-            )
+            let tile = (center.0 - (size.0 / 2) + x, center.1 - (size.1 / 2) + y);
+            if inside(tile.0, grid.len()) && inside(tile.1, grid[0].len()) {
+                add_tile(
+                    grid,
+                    tile.0 as usize,
+                    tile.1 as usize,
+                    tiletype,
+                    walkable,
+                    spawnable,
+                )
+            }
+        }
+    }
+}
+
+// Independent seed for each index, e.g. the areas of an act
+fn derive_seed(seed: u64, index: u64) -> u64 {
+    // splitmix64 finalizer
+    let mut z = seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Moves the start area, the spawn is on its first tile
+fn move_start(grid: &mut Grid, center: (usize, usize)) {
+    for column in grid.iter_mut() {
+        for tile in column.iter_mut() {
+            if tile.tile_type == TileType::Start {
+                tile.tile_type = TileType::Floor;
+            }
+        }
+    }
+    for column in &mut grid[center.0 - 2..=center.0 + 2] {
+        for tile in &mut column[center.1 - 2..=center.1 + 2] {
+            tile.tile_type = TileType::Start;
+            tile.walkable = true;
+            tile.spawnable = false;
         }
     }
 }
@@ -787,6 +1206,9 @@ fn render_grid(grid: &Grid, file_name: String, show_outline: bool) {
                         TileType::Event => image::Rgb([181u8, 181u8, 181u8]),
                         TileType::Water => image::Rgb([51u8, 114u8, 214u8]),
                         TileType::Forest => image::Rgb([42u8, 117u8, 14u8]),
+                        TileType::Exit => image::Rgb([230u8, 30u8, 30u8]),
+                        TileType::Waypoint => image::Rgb([40u8, 220u8, 230u8]),
+                        TileType::Checkpoint => image::Rgb([250u8, 230u8, 20u8]),
                     },
                 )
             };
@@ -795,3 +1217,106 @@ fn render_grid(grid: &Grid, file_name: String, show_outline: bool) {
     img.save("output/".to_string() + &file_name + ".png")
         .unwrap();
 }
+
+// Helpers shared by the tests of the submodules
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use super::*;
+
+    // one string per row, '.' for walkable floor and anything else for wall
+    pub(crate) fn grid_from(rows: &[&str]) -> Grid {
+        let mut grid = init_grid(rows.len() as i32, rows[0].len() as i32, TileType::Wall);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, character) in row.chars().enumerate() {
+                if character == '.' {
+                    grid[x][y].tile_type = TileType::Floor;
+                    grid[x][y].walkable = true;
+                }
+            }
+        }
+        grid
+    }
+
+    pub(crate) fn rows_of(grid: &Grid) -> Vec<String> {
+        (0..grid[0].len())
+            .map(|y| {
+                grid.iter()
+                    .map(|column| if column[y].walkable { '.' } else { '#' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // the walkable tiles of a generated area back on a grid
+    pub(crate) fn grid_of(area: &AreaGenerationOutput) -> Grid {
+        let mut grid = init_grid(area.height as i32, area.width as i32, TileType::Wall);
+        for (x, column) in grid.iter_mut().enumerate() {
+            for (y, tile) in column.iter_mut().enumerate() {
+                tile.walkable = area.walkable.get(x, y);
+                tile.tile_type = area.tiles[x * area.height as usize + y];
+            }
+        }
+        grid
+    }
+
+    pub(crate) fn generate(map_index: usize, seed: u64) -> AreaGenerationOutput {
+        generate_area(
+            &GenerationRequest::new(map_index, seed),
+            &GenerationConfig::headless(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::landmarks::LandmarkKind;
+
+    fn generate(rerolls: Rerolls) -> AreaGenerationOutput {
+        generate_area(
+            &GenerationRequest {
+                rerolls,
+                ..GenerationRequest::new(0, 5)
+            },
+            &GenerationConfig::headless(),
+        )
+    }
+
+    fn landmarks(area: &AreaGenerationOutput) -> Vec<(LandmarkKind, (u32, u32))> {
+        area.landmarks
+            .iter()
+            .map(|landmark| (landmark.kind, landmark.tile))
+            .collect()
+    }
+
+    fn packs(area: &AreaGenerationOutput) -> Vec<(u32, u32)> {
+        area.packs.iter().map(|pack| pack.tile).collect()
+    }
+
+    fn enemies(area: &AreaGenerationOutput) -> Vec<(String, (u32, u32))> {
+        area.enemies
+            .iter()
+            .map(|enemy| (enemy.monster_id.clone(), enemy.point))
+            .collect()
+    }
+
+    #[test]
+    fn rerolls_keep_the_earlier_stages() {
+        let base = generate(Rerolls::default());
+        let new_packs = generate(Rerolls {
+            packs: 1,
+            ..Rerolls::default()
+        });
+        assert_eq!(new_packs.tiles, base.tiles);
+        assert_eq!(landmarks(&new_packs), landmarks(&base));
+        assert_ne!(packs(&new_packs), packs(&base));
+
+        let new_mobs = generate(Rerolls {
+            mobs: 1,
+            ..Rerolls::default()
+        });
+        assert_eq!(new_mobs.tiles, base.tiles);
+        assert_eq!(packs(&new_mobs), packs(&base));
+        assert_ne!(enemies(&new_mobs), enemies(&base));
+    }
+}
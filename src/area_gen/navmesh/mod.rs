@@ -0,0 +1,455 @@
+// Navigation mesh built from the walkable tiles, for mob pathing on the server
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt::Write;
+
+use super::Grid;
+
+const EXPORT_VERSION: u32 = 1;
+
+// Left and right points of a portal, seen when walking through it
+type PortalSides = ((f32, f32), (f32, f32));
+
+pub struct NavMesh {
+    pub tile_size: i32,
+    pub polygons: Vec<NavPolygon>,
+}
+
+// Convex polygon in pixel space, points are clockwise on screen
+pub struct NavPolygon {
+    pub points: Vec<(f32, f32)>,
+    pub neighbours: Vec<Portal>,
+}
+
+// Edge shared with a neighbour polygon
+pub struct Portal {
+    pub polygon: usize,
+    pub start: (f32, f32),
+    pub end: (f32, f32),
+}
+
+// Rectangle of tiles, x and y are the coordinates of the top left tile
+#[derive(Clone, Copy)]
+pub(crate) struct TileRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl TileRect {
+    // tile x covers the pixels from (x - 1) * tile_size to x * tile_size
+    pub fn px_bounds(&self, tile_size: i32) -> ((f32, f32), (f32, f32)) {
+        let tile_size = tile_size as f32;
+        (
+            (
+                (self.x as f32 - 1.0) * tile_size,
+                (self.y as f32 - 1.0) * tile_size,
+            ),
+            (
+                (self.x + self.width - 1) as f32 * tile_size,
+                (self.y + self.height - 1) as f32 * tile_size,
+            ),
+        )
+    }
+}
+
+// Cover the tiles of the mask with rectangles, grown along x first then along y
+pub(crate) fn merge_rectangles(mask: &[Vec<bool>]) -> Vec<TileRect> {
+    let width = mask.len();
+    let height = mask[0].len();
+    let mut used = vec![vec![false; height]; width];
+    let mut rectangles = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if !mask[x][y] || used[x][y] {
+                continue;
+            }
+            let mut rect_width = 1;
+            while x + rect_width < width && mask[x + rect_width][y] && !used[x + rect_width][y] {
+                rect_width += 1;
+            }
+            let mut rect_height = 1;
+            'grow: while y + rect_height < height {
+                for dx in 0..rect_width {
+                    if !mask[x + dx][y + rect_height] || used[x + dx][y + rect_height] {
+                        break 'grow;
+                    }
+                }
+                rect_height += 1;
+            }
+            for dx in 0..rect_width {
+                for dy in 0..rect_height {
+                    used[x + dx][y + dy] = true;
+                }
+            }
+            rectangles.push(TileRect {
+                x,
+                y,
+                width: rect_width,
+                height: rect_height,
+            });
+        }
+    }
+    rectangles
+}
+
+impl NavMesh {
+    pub(crate) fn from_grid(grid: &Grid, tile_size: i32) -> NavMesh {
+        let mask: Vec<Vec<bool>> = grid
+            .iter()
+            .map(|row| row.iter().map(|tile| tile.walkable).collect())
+            .collect();
+        let rectangles = merge_rectangles(&mask);
+
+        // rectangle owning each tile
+        let mut owner = vec![vec![usize::MAX; mask[0].len()]; mask.len()];
+        for (index, rect) in rectangles.iter().enumerate() {
+            for column in &mut owner[rect.x..rect.x + rect.width] {
+                column[rect.y..rect.y + rect.height].fill(index);
+            }
+        }
+
+        let polygons = rectangles
+            .iter()
+            .map(|rect| {
+                let (min, max) = rect.px_bounds(tile_size);
+                let mut neighbours: Vec<Portal> = Vec::new();
+                // tiles just outside each side of the rectangle
+                let mut touching = Vec::new();
+                for y in rect.y..rect.y + rect.height {
+                    touching.push((rect.x + rect.width, y));
+                    touching.push((rect.x - 1, y));
+                }
+                for x in rect.x..rect.x + rect.width {
+                    touching.push((x, rect.y + rect.height));
+                    touching.push((x, rect.y - 1));
+                }
+                for (x, y) in touching {
+                    let other = owner[x][y];
+                    if other == usize::MAX || neighbours.iter().any(|p| p.polygon == other) {
+                        continue;
+                    }
+                    let (other_min, other_max) = rectangles[other].px_bounds(tile_size);
+                    let (start, end) = if other_min.0 == max.0 || other_max.0 == min.0 {
+                        let x = if other_min.0 == max.0 { max.0 } else { min.0 };
+                        ((x, min.1.max(other_min.1)), (x, max.1.min(other_max.1)))
+                    } else {
+                        let y = if other_min.1 == max.1 { max.1 } else { min.1 };
+                        ((min.0.max(other_min.0), y), (max.0.min(other_max.0), y))
+                    };
+                    neighbours.push(Portal {
+                        polygon: other,
+                        start,
+                        end,
+                    });
+                }
+                NavPolygon {
+                    points: vec![min, (max.0, min.1), max, (min.0, max.1)],
+                    neighbours,
+                }
+            })
+            .collect();
+        NavMesh {
+            tile_size,
+            polygons,
+        }
+    }
+
+    pub fn polygon_at(&self, point: (f32, f32)) -> Option<usize> {
+        self.polygons.iter().position(|polygon| {
+            let (min, max) = (polygon.points[0], polygon.points[2]);
+            point.0 >= min.0 && point.0 <= max.0 && point.1 >= min.1 && point.1 <= max.1
+        })
+    }
+
+    // Shortest path between two pixel positions, None if one of them is not walkable
+    // or if they are not connected
+    pub fn find_path(&self, start: (f32, f32), end: (f32, f32)) -> Option<Vec<(f32, f32)>> {
+        let start_polygon = self.polygon_at(start)?;
+        let end_polygon = self.polygon_at(end)?;
+        let corridor = self.find_corridor(start_polygon, end_polygon, start, end)?;
+
+        // portals along the corridor, with the left and right points seen when walking through
+        let mut portals = vec![(start, start)];
+        for pair in corridor.windows(2) {
+            let portal = self.polygons[pair[0]]
+                .neighbours
+                .iter()
+                .find(|portal| portal.polygon == pair[1])
+                .unwrap();
+            let center = self.center(pair[0]);
+            if triangle_area(center, portal.start, portal.end) > 0.0 {
+                portals.push((portal.start, portal.end));
+            } else {
+                portals.push((portal.end, portal.start));
+            }
+        }
+        portals.push((end, end));
+        Some(string_pull(&portals))
+    }
+
+    // A* on the polygons, moving through the middle of the portals
+    fn find_corridor(
+        &self,
+        start_polygon: usize,
+        end_polygon: usize,
+        start: (f32, f32),
+        end: (f32, f32),
+    ) -> Option<Vec<usize>> {
+        let mut cost = vec![f32::MAX; self.polygons.len()];
+        let mut position = vec![start; self.polygons.len()];
+        let mut came_from = vec![usize::MAX; self.polygons.len()];
+        let mut open = BinaryHeap::new();
+        cost[start_polygon] = 0.0;
+        open.push(OpenNode {
+            estimate: distance(start, end),
+            polygon: start_polygon,
+        });
+        while let Some(OpenNode { polygon, .. }) = open.pop() {
+            if polygon == end_polygon {
+                let mut corridor = vec![polygon];
+                while *corridor.last().unwrap() != start_polygon {
+                    corridor.push(came_from[*corridor.last().unwrap()]);
+                }
+                corridor.reverse();
+                return Some(corridor);
+            }
+            for portal in &self.polygons[polygon].neighbours {
+                let middle = (
+                    (portal.start.0 + portal.end.0) / 2.0,
+                    (portal.start.1 + portal.end.1) / 2.0,
+                );
+                let next_cost = cost[polygon] + distance(position[polygon], middle);
+                if next_cost < cost[portal.polygon] {
+                    cost[portal.polygon] = next_cost;
+                    position[portal.polygon] = middle;
+                    came_from[portal.polygon] = polygon;
+                    open.push(OpenNode {
+                        estimate: next_cost + distance(middle, end),
+                        polygon: portal.polygon,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn center(&self, polygon: usize) -> (f32, f32) {
+        let (min, max) = (
+            self.polygons[polygon].points[0],
+            self.polygons[polygon].points[2],
+        );
+        ((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0)
+    }
+
+    // Export for the server, see EXPORT_VERSION
+    // {"version":1,"tile_size":60,"polygons":[{"points":[[x,y],..],
+    //  "neighbours":[{"polygon":1,"portal":[[x,y],[x,y]]},..]},..]}
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write!(
+            json,
+            "{{\"version\":{},\"tile_size\":{},\"polygons\":[",
+            EXPORT_VERSION, self.tile_size
+        )
+        .unwrap();
+        for (index, polygon) in self.polygons.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"points\":[");
+            let points: Vec<String> = polygon
+                .points
+                .iter()
+                .map(|p| format!("[{},{}]", p.0, p.1))
+                .collect();
+            json.push_str(&points.join(","));
+            json.push_str("],\"neighbours\":[");
+            let neighbours: Vec<String> = polygon
+                .neighbours
+                .iter()
+                .map(|portal| {
+                    format!(
+                        "{{\"polygon\":{},\"portal\":[[{},{}],[{},{}]]}}",
+                        portal.polygon, portal.start.0, portal.start.1, portal.end.0, portal.end.1
+                    )
+                })
+                .collect();
+            json.push_str(&neighbours.join(","));
+            json.push_str("]}");
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+// Simple stupid funnel algorithm, portals are (left, right) pairs
+fn string_pull(portals: &[PortalSides]) -> Vec<(f32, f32)> {
+    let mut path = vec![portals[0].0];
+    let (mut apex, mut left, mut right) = (portals[0].0, portals[0].0, portals[0].1);
+    let (mut left_index, mut right_index) = (0, 0);
+    let mut i = 1;
+    while i < portals.len() {
+        let (portal_left, portal_right) = portals[i];
+        // tighten the right side of the funnel
+        if triangle_area(apex, right, portal_right) <= 0.0 {
+            if apex == right || triangle_area(apex, left, portal_right) > 0.0 {
+                right = portal_right;
+                right_index = i;
+            } else {
+                // right crossed left, left becomes a corner of the path
+                path.push(left);
+                apex = left;
+                let apex_index = left_index;
+                right = apex;
+                right_index = apex_index;
+                i = apex_index + 1;
+                continue;
+            }
+        }
+        // tighten the left side of the funnel
+        if triangle_area(apex, left, portal_left) >= 0.0 {
+            if apex == left || triangle_area(apex, right, portal_left) < 0.0 {
+                left = portal_left;
+                left_index = i;
+            } else {
+                path.push(right);
+                apex = right;
+                let apex_index = right_index;
+                left = apex;
+                left_index = apex_index;
+                i = apex_index + 1;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    let end = portals[portals.len() - 1].0;
+    if *path.last().unwrap() != end {
+        path.push(end);
+    }
+    path
+}
+
+// Positive when c is on the right of the line from a to b, in the funnel point of view
+fn triangle_area(a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> f32 {
+    (c.0 - a.0) * (b.1 - a.1) - (b.0 - a.0) * (c.1 - a.1)
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+struct OpenNode {
+    estimate: f32,
+    polygon: usize,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    // reversed, the binary heap pops the smallest estimate first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::test_helpers::grid_from;
+    use crate::area_gen::tile_center;
+
+    const TILE_SIZE: i32 = 10;
+
+    fn mesh(rows: &[&str]) -> NavMesh {
+        NavMesh::from_grid(&grid_from(rows), TILE_SIZE)
+    }
+
+    fn center(tile: (usize, usize)) -> (f32, f32) {
+        tile_center(tile, TILE_SIZE)
+    }
+
+    #[test]
+    fn straight_corridors_are_two_points() {
+        let mesh = mesh(&[
+            "#########",
+            "#...#...#",
+            "#.......#",
+            "#...#...#",
+            "#########",
+        ]);
+        assert_eq!(mesh.polygons.len(), 3);
+        let (start, end) = (center((1, 2)), center((7, 2)));
+        assert_eq!(mesh.find_path(start, end), Some(vec![start, end]));
+        assert_eq!(mesh.find_path(end, start), Some(vec![end, start]));
+    }
+
+    #[test]
+    fn bends_turn_at_the_inner_corner() {
+        let mesh = mesh(&[
+            "#######", "#.....#", "#.....#", "#..####", "#..####", "#..####", "#######",
+        ]);
+        // wall tile (3, 3) starts at pixel (20, 20)
+        let corner = (20.0, 20.0);
+        let (start, end) = (center((1, 5)), center((5, 1)));
+        assert_eq!(mesh.find_path(start, end), Some(vec![start, corner, end]));
+        assert_eq!(mesh.find_path(end, start), Some(vec![end, corner, start]));
+    }
+
+    #[test]
+    fn points_on_shared_edges_belong_to_a_polygon() {
+        let mesh = mesh(&[
+            "#######", "#.....#", "#.....#", "#..####", "#..####", "#######",
+        ]);
+        // between the two rectangles
+        let edge = (10.0, 20.0);
+        let polygon = mesh.polygon_at(edge).unwrap();
+        let (min, max) = (
+            mesh.polygons[polygon].points[0],
+            mesh.polygons[polygon].points[2],
+        );
+        assert!(min.0 <= edge.0 && edge.0 <= max.0 && min.1 <= edge.1 && edge.1 <= max.1);
+        let end = center((5, 1));
+        assert_eq!(mesh.find_path(edge, end), Some(vec![edge, end]));
+    }
+
+    #[test]
+    fn unreachable_and_outside_points_have_no_path() {
+        let mesh = mesh(&["#######", "#..#..#", "#..#..#", "#######"]);
+        let (left, right) = (center((1, 1)), center((5, 1)));
+        assert_eq!(mesh.find_path(left, right), None);
+        // in a wall, and outside of the area
+        assert_eq!(mesh.find_path(left, center((3, 1))), None);
+        assert_eq!(mesh.find_path((-100.0, -100.0), left), None);
+        assert_eq!(mesh.find_path(left, (1000.0, 5.0)), None);
+    }
+
+    #[test]
+    fn json_export() {
+        let mesh = mesh(&["####", "#..#", "#.##", "####"]);
+        assert_eq!(
+            mesh.to_json(),
+            concat!(
+                r#"{"version":1,"tile_size":10,"polygons":["#,
+                r#"{"points":[[0,0],[20,0],[20,10],[0,10]],"#,
+                r#""neighbours":[{"polygon":1,"portal":[[0,10],[10,10]]}]},"#,
+                r#"{"points":[[0,10],[10,10],[10,20],[0,20]],"#,
+                r#""neighbours":[{"polygon":0,"portal":[[0,10],[10,10]]}]}]}"#,
+            )
+        );
+    }
+}
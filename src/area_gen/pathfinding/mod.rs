@@ -0,0 +1,427 @@
+// A* path finding on the tile grid, usable during generation and on the output
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use super::{tile_center, AreaGenerationOutput, Grid};
+use crate::area_gen::maps::TileType;
+
+// Anything that can be read as a grid of tiles
+pub trait TileMap {
+    fn size(&self) -> (usize, usize);
+    fn is_walkable(&self, x: usize, y: usize) -> bool;
+    fn tile_type(&self, x: usize, y: usize) -> TileType;
+    // size of a tile in pixels, for the points of the paths
+    fn tile_size(&self) -> i32;
+}
+
+// the grid does not know the tile size of the generation, it goes along with it
+impl TileMap for (&Grid, i32) {
+    fn size(&self) -> (usize, usize) {
+        (self.0.len(), self.0[0].len())
+    }
+    fn is_walkable(&self, x: usize, y: usize) -> bool {
+        self.0[x][y].walkable
+    }
+    fn tile_type(&self, x: usize, y: usize) -> TileType {
+        self.0[x][y].tile_type
+    }
+    fn tile_size(&self) -> i32 {
+        self.1
+    }
+}
+
+impl TileMap for AreaGenerationOutput {
+    fn size(&self) -> (usize, usize) {
+        (self.width as usize, self.height as usize)
+    }
+    fn is_walkable(&self, x: usize, y: usize) -> bool {
+        self.walkable.get(x, y)
+    }
+    fn tile_type(&self, x: usize, y: usize) -> TileType {
+        self.tiles[x * self.height as usize + y]
+    }
+    fn tile_size(&self) -> i32 {
+        self.tile_size
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    Four,
+    Eight,
+}
+
+// When moving diagonally, which of the two tiles on the sides must be walkable
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CornerCutting {
+    // both side tiles
+    Never,
+    // at least one side tile
+    IfOneSideFree,
+    // none, the path can squeeze between two oob tiles
+    Always,
+}
+
+#[derive(Clone)]
+pub struct PathOptions {
+    pub connectivity: Connectivity,
+    pub corner_cutting: CornerCutting,
+    // cost multiplier to enter a tile of that type, 1.0 when missing, infinite to forbid it
+    // negative or nan costs make the options invalid, nothing can be reached with them
+    pub tile_costs: HashMap<TileType, f32>,
+}
+
+impl Default for PathOptions {
+    fn default() -> Self {
+        PathOptions {
+            connectivity: Connectivity::Eight,
+            corner_cutting: CornerCutting::Never,
+            tile_costs: HashMap::new(),
+        }
+    }
+}
+
+impl PathOptions {
+    pub fn is_valid(&self) -> bool {
+        self.tile_costs.values().all(|cost| *cost >= 0.0)
+    }
+}
+
+pub struct Path {
+    pub tiles: Vec<(usize, usize)>,
+    // center of each tile in pixels
+    pub points: Vec<(f32, f32)>,
+    pub cost: f32,
+}
+
+pub fn find_path<M: TileMap>(
+    map: &M,
+    start: (usize, usize),
+    goal: (usize, usize),
+    options: &PathOptions,
+) -> Option<Path> {
+    let (width, height) = map.size();
+    if start.0 >= width || start.1 >= height || goal.0 >= width || goal.1 >= height {
+        return None;
+    }
+    if !map.is_walkable(start.0, start.1) || !map.is_walkable(goal.0, goal.1) || !options.is_valid()
+    {
+        return None;
+    }
+    // cheapest tile cost keeps the heuristic admissible
+    let min_cost = options
+        .tile_costs
+        .values()
+        .fold(1.0f32, |min, cost| min.min(*cost));
+    let heuristic = |x: usize, y: usize| {
+        let dx = (x as f32 - goal.0 as f32).abs();
+        let dy = (y as f32 - goal.1 as f32).abs();
+        let distance = match options.connectivity {
+            Connectivity::Four => dx + dy,
+            Connectivity::Eight => dx.max(dy) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dy),
+        };
+        distance * min_cost
+    };
+
+    let index = |x: usize, y: usize| x * height + y;
+    let mut cost = vec![f32::INFINITY; width * height];
+    let mut came_from = vec![usize::MAX; width * height];
+    let mut open = BinaryHeap::new();
+    cost[index(start.0, start.1)] = 0.0;
+    open.push(OpenTile {
+        estimate: heuristic(start.0, start.1),
+        tile: start,
+    });
+
+    while let Some(OpenTile { tile, estimate }) = open.pop() {
+        let current_cost = cost[index(tile.0, tile.1)];
+        // outdated entry, the tile was reached cheaper since
+        if estimate > current_cost + heuristic(tile.0, tile.1) {
+            continue;
+        }
+        if tile == goal {
+            let mut tiles = vec![goal];
+            while *tiles.last().unwrap() != start {
+                let last = *tiles.last().unwrap();
+                let previous = came_from[index(last.0, last.1)];
+                tiles.push((previous / height, previous % height));
+            }
+            tiles.reverse();
+            let points = tiles
+                .iter()
+                .map(|tile| tile_center(*tile, map.tile_size()))
+                .collect();
+            return Some(Path {
+                tiles,
+                points,
+                cost: current_cost,
+            });
+        }
+        for_each_neighbour(map, tile, options, |next, step_cost| {
+            let next_cost = current_cost + step_cost;
+            if next_cost < cost[index(next.0, next.1)] {
+                cost[index(next.0, next.1)] = next_cost;
+                came_from[index(next.0, next.1)] = index(tile.0, tile.1);
+                open.push(OpenTile {
+                    estimate: next_cost + heuristic(next.0, next.1),
+                    tile: next,
+                });
+            }
+        });
+    }
+    None
+}
+
+// Walking cost from the start to every tile, infinite for the tiles that can not be reached
+// The index of tile (x, y) is x * height + y
+pub fn distance_map<M: TileMap>(map: &M, start: (usize, usize), options: &PathOptions) -> Vec<f32> {
+    let (width, height) = map.size();
+    let index = |x: usize, y: usize| x * height + y;
+    let mut cost = vec![f32::INFINITY; width * height];
+    if start.0 >= width
+        || start.1 >= height
+        || !map.is_walkable(start.0, start.1)
+        || !options.is_valid()
+    {
+        return cost;
+    }
+    let mut open = BinaryHeap::new();
+    cost[index(start.0, start.1)] = 0.0;
+    open.push(OpenTile {
+        estimate: 0.0,
+        tile: start,
+    });
+    while let Some(OpenTile { tile, estimate }) = open.pop() {
+        if estimate > cost[index(tile.0, tile.1)] {
+            continue;
+        }
+        for_each_neighbour(map, tile, options, |next, step_cost| {
+            let next_cost = estimate + step_cost;
+            if next_cost < cost[index(next.0, next.1)] {
+                cost[index(next.0, next.1)] = next_cost;
+                open.push(OpenTile {
+                    estimate: next_cost,
+                    tile: next,
+                });
+            }
+        });
+    }
+    cost
+}
+
+// Calls visit with each tile that can be entered from the tile, and the cost to enter it
+fn for_each_neighbour<M: TileMap, F: FnMut((usize, usize), f32)>(
+    map: &M,
+    tile: (usize, usize),
+    options: &PathOptions,
+    mut visit: F,
+) {
+    let (width, height) = map.size();
+    let directions: &[(i32, i32)] = match options.connectivity {
+        Connectivity::Four => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+        Connectivity::Eight => &[
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ],
+    };
+    for direction in directions {
+        let x = tile.0 as i32 + direction.0;
+        let y = tile.1 as i32 + direction.1;
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            continue;
+        }
+        let (x, y) = (x as usize, y as usize);
+        if !map.is_walkable(x, y) {
+            continue;
+        }
+        let diagonal = direction.0 != 0 && direction.1 != 0;
+        if diagonal {
+            let side_x = map.is_walkable(x, tile.1);
+            let side_y = map.is_walkable(tile.0, y);
+            let allowed = match options.corner_cutting {
+                CornerCutting::Never => side_x && side_y,
+                CornerCutting::IfOneSideFree => side_x || side_y,
+                CornerCutting::Always => true,
+            };
+            if !allowed {
+                continue;
+            }
+        }
+        let tile_cost = *options.tile_costs.get(&map.tile_type(x, y)).unwrap_or(&1.0);
+        if !tile_cost.is_finite() {
+            continue;
+        }
+        let step = if diagonal {
+            std::f32::consts::SQRT_2
+        } else {
+            1.0
+        };
+        visit((x, y), step * tile_cost);
+    }
+}
+
+struct OpenTile {
+    estimate: f32,
+    tile: (usize, usize),
+}
+
+impl PartialEq for OpenTile {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenTile {}
+
+impl PartialOrd for OpenTile {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenTile {
+    // reversed, the binary heap pops the smallest estimate first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::init_grid;
+    use crate::area_gen::test_helpers::grid_from;
+
+    fn options(connectivity: Connectivity, corner_cutting: CornerCutting) -> PathOptions {
+        PathOptions {
+            connectivity,
+            corner_cutting,
+            tile_costs: HashMap::new(),
+        }
+    }
+
+    fn maze() -> Grid {
+        grid_from(&[
+            "........#.",
+            ".######.#.",
+            ".#....#.#.",
+            ".#.##.#...",
+            "...#..###.",
+            "####.#....",
+            ".....#.##.",
+            "..#..#..#.",
+        ])
+    }
+
+    #[test]
+    fn path_points_use_the_tile_size_of_the_grid() {
+        let mut grid = init_grid(3, 3, TileType::Wall);
+        for column in grid.iter_mut() {
+            column[1].walkable = true;
+            column[1].tile_type = TileType::Floor;
+        }
+        for tile_size in [20, 60, 90] {
+            let path =
+                find_path(&(&grid, tile_size), (0, 1), (2, 1), &PathOptions::default()).unwrap();
+            assert_eq!(path.tiles, vec![(0, 1), (1, 1), (2, 1)]);
+            let expected: Vec<(f32, f32)> = path
+                .tiles
+                .iter()
+                .map(|tile| tile_center(*tile, tile_size))
+                .collect();
+            assert_eq!(path.points, expected);
+        }
+    }
+
+    #[test]
+    fn four_connectivity_never_steps_diagonally() {
+        let grid = grid_from(&["......", "......", "......", "......"]);
+        let four = options(Connectivity::Four, CornerCutting::Always);
+        let path = find_path(&(&grid, 10), (0, 0), (5, 3), &four).unwrap();
+        for pair in path.tiles.windows(2) {
+            let (dx, dy) = (pair[0].0.abs_diff(pair[1].0), pair[0].1.abs_diff(pair[1].1));
+            assert_eq!(dx + dy, 1);
+        }
+        assert_eq!(path.cost, 8.0);
+    }
+
+    #[test]
+    fn corner_cutting_decides_the_diagonal_squeezes() {
+        let both_blocked = grid_from(&[".#", "#."]);
+        let one_blocked = grid_from(&["..", "#."]);
+        let squeeze = |grid: &Grid, corner_cutting| {
+            find_path(
+                &(grid, 10),
+                (0, 0),
+                (1, 1),
+                &options(Connectivity::Eight, corner_cutting),
+            )
+            .map(|path| path.tiles.len())
+        };
+        assert_eq!(squeeze(&both_blocked, CornerCutting::Never), None);
+        assert_eq!(squeeze(&both_blocked, CornerCutting::IfOneSideFree), None);
+        assert_eq!(squeeze(&both_blocked, CornerCutting::Always), Some(2));
+        assert_eq!(squeeze(&one_blocked, CornerCutting::Never), Some(3));
+        assert_eq!(squeeze(&one_blocked, CornerCutting::IfOneSideFree), Some(2));
+    }
+
+    #[test]
+    fn expensive_tiles_are_walked_around() {
+        let mut grid = grid_from(&[".......", ".......", "......."]);
+        for column in &mut grid[1..6] {
+            column[1].tile_type = TileType::Water;
+        }
+        let mut water = PathOptions::default();
+        water.tile_costs.insert(TileType::Water, 5.0);
+        let path = find_path(&(&grid, 10), (0, 1), (6, 1), &water).unwrap();
+        assert!(path
+            .tiles
+            .iter()
+            .all(|(x, y)| grid[*x][*y].tile_type != TileType::Water));
+        // through the water it would cost 1 + 5 * 5
+        assert!(path.cost < 26.0);
+        water.tile_costs.insert(TileType::Water, f32::INFINITY);
+        assert!(find_path(&(&grid, 10), (0, 1), (3, 1), &water).is_none());
+    }
+
+    #[test]
+    fn distance_map_matches_the_path_costs() {
+        let mut grid = maze();
+        grid[9][0].tile_type = TileType::Forest;
+        grid[4][6].tile_type = TileType::Forest;
+        let mut forest = options(Connectivity::Eight, CornerCutting::IfOneSideFree);
+        forest.tile_costs.insert(TileType::Forest, 3.0);
+        for options in [PathOptions::default(), forest] {
+            let distances = distance_map(&(&grid, 10), (0, 0), &options);
+            for x in 0..grid.len() {
+                for y in 0..grid[0].len() {
+                    let path = find_path(&(&grid, 10), (0, 0), (x, y), &options);
+                    match path {
+                        Some(path) => assert!((path.cost - distances[x * 8 + y]).abs() < 1e-4),
+                        None => assert!(distances[x * 8 + y].is_infinite()),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn negative_costs_are_rejected() {
+        let grid = maze();
+        let mut options = PathOptions::default();
+        options.tile_costs.insert(TileType::Forest, -1.0);
+        assert!(!options.is_valid());
+        assert!(find_path(&(&grid, 10), (0, 0), (1, 0), &options).is_none());
+        assert!(distance_map(&(&grid, 10), (0, 0), &options)
+            .iter()
+            .all(|distance| distance.is_infinite()));
+        options.tile_costs.insert(TileType::Forest, f32::NAN);
+        assert!(!options.is_valid());
+    }
+}
@@ -0,0 +1,850 @@
+// Simplification of the oob polygons traced on the tile grid
+use super::Shape;
+
+#[derive(Clone, Copy, Debug)]
+pub enum PolygonSimplification {
+    // keep every corner of the tile staircase
+    None,
+    // remove points lying on a straight line between their neighbours
+    MergeCollinear,
+    // Douglas-Peucker, tolerance is the max distance in pixels to the traced polygon
+    DouglasPeucker(f32),
+    // replace tile staircases by diagonal segments
+    StaircaseToDiagonal,
+}
+
+// Simplify each shape, outer shapes can only grow and inner shapes only shrink,
+// so the walkable area is never reduced, and no shape can cross itself or another one
+pub fn simplify_shapes(shapes: &mut [Shape], mode: PolygonSimplification, tile_size: i32) {
+    let mut obstacles: Vec<Obstacle> = shapes
+        .iter()
+        .map(|shape| Obstacle::new(&shape.points))
+        .collect();
+    for (index, shape) in shapes.iter_mut().enumerate() {
+        // a shape is not an obstacle to itself
+        obstacles[index] = Obstacle::new(&[]);
+        shape.points = simplify_among(
+            &shape.points,
+            !shape.inner_if_true,
+            mode,
+            tile_size,
+            &obstacles,
+        );
+        obstacles[index] = Obstacle::new(&shape.points);
+    }
+}
+
+pub fn simplify_polygon(
+    points: &[(f32, f32)],
+    walkable_inside: bool,
+    mode: PolygonSimplification,
+    tile_size: i32,
+) -> Vec<(f32, f32)> {
+    simplify_among(points, walkable_inside, mode, tile_size, &[])
+}
+
+// Simplify a polygon without crossing or cutting off any of the obstacles
+fn simplify_among(
+    points: &[(f32, f32)],
+    walkable_inside: bool,
+    mode: PolygonSimplification,
+    tile_size: i32,
+    obstacles: &[Obstacle],
+) -> Vec<(f32, f32)> {
+    if points.len() < 4 || matches!(mode, PolygonSimplification::None) {
+        return points.to_vec();
+    }
+    let points: Vec<(f64, f64)> = points.iter().map(|p| (p.0 as f64, p.1 as f64)).collect();
+    // sign of the cross product for a point on the side of an edge where the walkable area is
+    let area_sign = signed_area(&points).signum();
+    let walkable_sign = if walkable_inside {
+        area_sign
+    } else {
+        -area_sign
+    };
+    let mut polygon = WorkPolygon {
+        kept: vec![true; points.len()],
+        points,
+        walkable_sign,
+        tile_size: tile_size as f64,
+        obstacles,
+    };
+    let collinear = |polygon: &WorkPolygon, i, j| polygon.max_distance(i, j).1 < 1e-6;
+    match mode {
+        // returned as they are above
+        PolygonSimplification::None => {}
+        PolygonSimplification::MergeCollinear => polygon.greedy(collinear),
+        PolygonSimplification::DouglasPeucker(tolerance) => {
+            let n = polygon.points.len();
+            // split the closed polygon on the point farthest from the first one
+            let start = polygon.points[0];
+            let mut farthest = 1;
+            for k in 2..n {
+                if distance_sq(polygon.points[k], start)
+                    > distance_sq(polygon.points[farthest], start)
+                {
+                    farthest = k;
+                }
+            }
+            polygon.douglas_peucker(0, farthest, tolerance as f64);
+            polygon.douglas_peucker(farthest, n, tolerance as f64);
+            // the kept points can still be on a straight line
+            polygon.greedy(collinear);
+        }
+        PolygonSimplification::StaircaseToDiagonal => {
+            polygon.greedy(|polygon, i, j| polygon.is_staircase(i, j));
+            polygon.greedy(collinear);
+        }
+    }
+
+    polygon
+        .points
+        .iter()
+        .zip(polygon.kept.iter())
+        .filter(|(_, kept)| **kept)
+        .map(|(p, _)| (p.0 as f32, p.1 as f32))
+        .collect()
+}
+
+// The other shapes, already simplified or not yet
+struct Obstacle {
+    points: Vec<(f64, f64)>,
+    min: (f64, f64),
+    max: (f64, f64),
+}
+
+impl Obstacle {
+    fn new(points: &[(f32, f32)]) -> Obstacle {
+        let points: Vec<(f64, f64)> = points.iter().map(|p| (p.0 as f64, p.1 as f64)).collect();
+        let (min, max) = bounds(&points);
+        Obstacle { points, min, max }
+    }
+}
+
+struct WorkPolygon<'a> {
+    points: Vec<(f64, f64)>,
+    kept: Vec<bool>,
+    walkable_sign: f64,
+    // staircase steps are one tile long
+    tile_size: f64,
+    obstacles: &'a [Obstacle],
+}
+
+impl WorkPolygon<'_> {
+    fn point(&self, index: usize) -> (f64, f64) {
+        self.points[index % self.points.len()]
+    }
+
+    fn kept_count(&self) -> usize {
+        self.kept.iter().filter(|kept| **kept).count()
+    }
+
+    // Walk the polygon, from each kept point jump to the farthest point
+    // the chain in between can be replaced with
+    fn greedy<F: Fn(&WorkPolygon, usize, usize) -> bool>(&mut self, chain_ok: F) {
+        let n = self.points.len();
+        let mut i = 0;
+        while i < n {
+            let mut best = i + 1;
+            while best < n && !self.kept[best] {
+                best += 1;
+            }
+            let mut j = best + 1;
+            while j <= n {
+                if j < n && !self.kept[j] {
+                    j += 1;
+                    continue;
+                }
+                if !chain_ok(self, i, j) || !self.can_replace(i, j) {
+                    break;
+                }
+                best = j;
+                j += 1;
+            }
+            self.remove_between(i, best);
+            i = best;
+        }
+    }
+
+    // Douglas-Peucker on the chain from i to j (j can be n, the first point again)
+    fn douglas_peucker(&mut self, i: usize, j: usize, tolerance: f64) {
+        if j <= i + 1 {
+            return;
+        }
+        let (farthest, distance) = self.max_distance(i, j);
+        let split = match self.worst_side_violation(i, j) {
+            Some(violation) => violation,
+            None if distance <= tolerance && self.can_replace(i, j) => {
+                self.remove_between(i, j);
+                return;
+            }
+            None => farthest,
+        };
+        self.douglas_peucker(i, split, tolerance);
+        self.douglas_peucker(split, j, tolerance);
+    }
+
+    // A chain can be replaced by a segment if it does not reduce the walkable area,
+    // does not cross the rest of the polygon, and leaves a real polygon
+    fn can_replace(&self, i: usize, j: usize) -> bool {
+        let removed = (i + 1..j).filter(|k| self.kept[*k]).count();
+        removed == 0
+            || (self.kept_count() - removed >= 3
+                && self.worst_side_violation(i, j).is_none()
+                && !self.crosses_polygon(i, j)
+                && !self.crosses_obstacles(i, j))
+    }
+
+    fn remove_between(&mut self, i: usize, j: usize) {
+        for k in (i + 1)..j {
+            self.kept[k] = false;
+        }
+    }
+
+    // Kept point of the chain with the biggest distance to the segment, and that distance
+    fn max_distance(&self, i: usize, j: usize) -> (usize, f64) {
+        let (a, b) = (self.point(i), self.point(j));
+        let mut farthest = (i + 1, -1.0);
+        for k in (i + 1)..j {
+            if self.kept[k] {
+                let distance = distance_to_segment(self.points[k], a, b);
+                if distance > farthest.1 {
+                    farthest = (k, distance);
+                }
+            }
+        }
+        farthest
+    }
+
+    // Kept point of the chain that is the farthest on the walkable side of the segment
+    fn worst_side_violation(&self, i: usize, j: usize) -> Option<usize> {
+        let (a, b) = (self.point(i), self.point(j));
+        let mut worst: Option<(usize, f64)> = None;
+        for k in (i + 1)..j {
+            if self.kept[k] {
+                let side = cross(a, b, self.points[k]) * self.walkable_sign;
+                if side < -1e-6 && worst.is_none_or(|(_, value)| side < value) {
+                    worst = Some((k, side));
+                }
+            }
+        }
+        worst.map(|(k, _)| k)
+    }
+
+    // Chains of axis aligned edges going in a single diagonal direction,
+    // with every point within a tile of the segment
+    fn is_staircase(&self, i: usize, j: usize) -> bool {
+        let mut direction = (0.0, 0.0);
+        let mut previous = self.point(i);
+        for k in (i + 1)..=j {
+            if k < self.points.len() && !self.kept[k] {
+                continue;
+            }
+            let current = self.point(k);
+            let step = (current.0 - previous.0, current.1 - previous.1);
+            if step.0 != 0.0 && step.1 != 0.0 {
+                return false;
+            }
+            for (axis_step, axis_direction) in
+                [(step.0, &mut direction.0), (step.1, &mut direction.1)]
+            {
+                if axis_step != 0.0 {
+                    if *axis_direction == 0.0 {
+                        *axis_direction = axis_step.signum();
+                    } else if *axis_direction != axis_step.signum() {
+                        return false;
+                    }
+                }
+            }
+            previous = current;
+        }
+        self.max_distance(i, j).1 <= self.tile_size
+    }
+
+    // Does the segment from i to j touch an edge of the polygon, ignoring the replaced chain
+    fn crosses_polygon(&self, i: usize, j: usize) -> bool {
+        let n = self.points.len();
+        let (a, b) = (self.point(i), self.point(j));
+        let kept: Vec<usize> = (0..n)
+            .filter(|k| self.kept[*k] && (*k <= i || *k >= j))
+            .collect();
+        for (index, start) in kept.iter().enumerate() {
+            let end = kept[(index + 1) % kept.len()];
+            // the segment itself
+            if *start == i {
+                continue;
+            }
+            let (c, d) = (self.points[*start], self.points[end]);
+            let shares_a = *start == i || end == i;
+            let shares_b = *start == j % n || end == j % n;
+            if shares_a || shares_b {
+                // neighbouring edges can only go back along the segment
+                let (shared, other) = if *start == i || *start == j % n {
+                    (c, d)
+                } else {
+                    (d, c)
+                };
+                let toward = if shares_a { b } else { a };
+                if cross(shared, toward, other).abs() < 1e-6 && dot(shared, toward, other) > 0.0 {
+                    return true;
+                }
+                continue;
+            }
+            if segments_intersect(a, b, c, d) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Does the segment from i to j cross another shape, or cut one off with the chain it replaces
+    fn crosses_obstacles(&self, i: usize, j: usize) -> bool {
+        let (a, b) = (self.point(i), self.point(j));
+        let chain: Vec<(f64, f64)> = (i..=j)
+            .filter(|k| *k == i || *k == j || self.kept[*k])
+            .map(|k| self.point(k))
+            .collect();
+        let (min, max) = bounds(&chain);
+        self.obstacles
+            .iter()
+            .filter(|obstacle| {
+                obstacle.min.0 <= max.0
+                    && min.0 <= obstacle.max.0
+                    && obstacle.min.1 <= max.1
+                    && min.1 <= obstacle.max.1
+            })
+            .any(|obstacle| {
+                let n = obstacle.points.len();
+                (0..n).any(|k| {
+                    segments_intersect(a, b, obstacle.points[k], obstacle.points[(k + 1) % n])
+                })
+                    // shapes do not cross the chain, one of its points tells if it is cut off
+                    || point_in_polygon(obstacle.points[0], &chain)
+            })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ShapeError {
+    // shape index, a polygon needs at least 3 points
+    TooFewPoints(usize),
+    // shape index
+    SelfIntersection(usize),
+    // indexes of both shapes
+    ShapesIntersect(usize, usize),
+    // shape index, the point order does not match the shape depth
+    WrongWinding(usize),
+}
+
+// Link each shape to the smallest shape containing it, set the inner flag from
+// the nesting depth, and reverse the shapes that do not follow the winding convention
+pub fn build_hierarchy(shapes: &mut [Shape]) {
+    let points: Vec<Vec<(f64, f64)>> = shapes
+        .iter()
+        .map(|shape| {
+            shape
+                .points
+                .iter()
+                .map(|p| (p.0 as f64, p.1 as f64))
+                .collect()
+        })
+        .collect();
+    let areas: Vec<f64> = points.iter().map(|p| signed_area(p).abs()).collect();
+    // biggest shapes first, so parents always get their depth before their children
+    let mut order: Vec<usize> = (0..shapes.len()).collect();
+    order.sort_by(|a, b| areas[*b].total_cmp(&areas[*a]));
+
+    for shape in shapes.iter_mut() {
+        shape.parent = None;
+        shape.children = Vec::new();
+    }
+    for (rank, &index) in order.iter().enumerate() {
+        let parent = order[..rank]
+            .iter()
+            .rev()
+            .find(|&&candidate| contains(&points[candidate], &points[index]))
+            .copied();
+        shapes[index].parent = parent;
+        shapes[index].depth = match parent {
+            Some(parent) => shapes[parent].depth + 1,
+            None => 0,
+        };
+        if let Some(parent) = parent {
+            shapes[parent].children.push(index);
+        }
+    }
+
+    for (index, shape) in shapes.iter_mut().enumerate() {
+        shape.inner_if_true = shape.depth % 2 == 1;
+        let walkable_inside = !shape.inner_if_true;
+        if (signed_area(&points[index]) > 0.0) != walkable_inside {
+            shape.points.reverse();
+        }
+    }
+}
+
+// Check that no shape crosses itself or another one, and that the winding follows the depth
+pub fn validate_shapes(shapes: &[Shape]) -> Result<(), ShapeError> {
+    let points: Vec<Vec<(f64, f64)>> = shapes
+        .iter()
+        .map(|shape| {
+            shape
+                .points
+                .iter()
+                .map(|p| (p.0 as f64, p.1 as f64))
+                .collect()
+        })
+        .collect();
+    for (index, shape) in shapes.iter().enumerate() {
+        if shape.points.len() < 3 {
+            return Err(ShapeError::TooFewPoints(index));
+        }
+        if self_intersects(&points[index]) {
+            return Err(ShapeError::SelfIntersection(index));
+        }
+        if (signed_area(&points[index]) > 0.0) == shape.inner_if_true {
+            return Err(ShapeError::WrongWinding(index));
+        }
+    }
+    for first in 0..shapes.len() {
+        for second in (first + 1)..shapes.len() {
+            if polygons_intersect(&points[first], &points[second]) {
+                return Err(ShapeError::ShapesIntersect(first, second));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn self_intersects(points: &[(f64, f64)]) -> bool {
+    let n = points.len();
+    for i in 0..n {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        // going back along the previous edge
+        let previous = points[(i + n - 1) % n];
+        if cross(previous, a, b).abs() < 1e-6 && dot(a, previous, b) > 0.0 {
+            return true;
+        }
+        for j in (i + 2)..n {
+            if i == 0 && j == n - 1 {
+                continue;
+            }
+            if segments_intersect(a, b, points[j], points[(j + 1) % n]) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+pub(crate) fn polygons_intersect(first: &[(f64, f64)], second: &[(f64, f64)]) -> bool {
+    let (first_min, first_max) = bounds(first);
+    let (second_min, second_max) = bounds(second);
+    if first_max.0 < second_min.0
+        || second_max.0 < first_min.0
+        || first_max.1 < second_min.1
+        || second_max.1 < first_min.1
+    {
+        return false;
+    }
+    for i in 0..first.len() {
+        let (a, b) = (first[i], first[(i + 1) % first.len()]);
+        for j in 0..second.len() {
+            if segments_intersect(a, b, second[j], second[(j + 1) % second.len()]) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+fn bounds(points: &[(f64, f64)]) -> ((f64, f64), (f64, f64)) {
+    points.iter().fold(
+        ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN)),
+        |(min, max), p| {
+            (
+                (min.0.min(p.0), min.1.min(p.1)),
+                (max.0.max(p.0), max.1.max(p.1)),
+            )
+        },
+    )
+}
+
+// Shapes do not cross, so the first point of inner that is not on the outer boundary decides
+fn contains(outer: &[(f64, f64)], inner: &[(f64, f64)]) -> bool {
+    let n = outer.len();
+    inner
+        .iter()
+        .find(|p| (0..n).all(|k| distance_to_segment(**p, outer[k], outer[(k + 1) % n]) > 1e-6))
+        .is_some_and(|p| point_in_polygon(*p, outer))
+}
+
+pub(crate) fn point_in_polygon(point: (f64, f64), polygon: &[(f64, f64)]) -> bool {
+    let mut inside = false;
+    let n = polygon.len();
+    for k in 0..n {
+        let (a, b) = (polygon[k], polygon[(k + 1) % n]);
+        if (a.1 > point.1) != (b.1 > point.1)
+            && point.0 < a.0 + (point.1 - a.1) * (b.0 - a.0) / (b.1 - a.1)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+pub(crate) fn signed_area(points: &[(f64, f64)]) -> f64 {
+    let mut area = 0.0;
+    for k in 0..points.len() {
+        let (a, b) = (points[k], points[(k + 1) % points.len()]);
+        area += a.0 * b.1 - b.0 * a.1;
+    }
+    area / 2.0
+}
+
+// Cross product of (b - a) and (p - a)
+pub(crate) fn cross(a: (f64, f64), b: (f64, f64), p: (f64, f64)) -> f64 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+fn dot(a: (f64, f64), b: (f64, f64), p: (f64, f64)) -> f64 {
+    (b.0 - a.0) * (p.0 - a.0) + (b.1 - a.1) * (p.1 - a.1)
+}
+
+fn distance_sq(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)
+}
+
+pub(crate) fn distance_to_segment(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let length_sq = distance_sq(a, b);
+    if length_sq == 0.0 {
+        return distance_sq(p, a).sqrt();
+    }
+    let t = (dot(a, b, p) / length_sq).clamp(0.0, 1.0);
+    distance_sq(p, (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1))).sqrt()
+}
+
+// True when the segments share at least one point
+pub(crate) fn segments_intersect(
+    a: (f64, f64),
+    b: (f64, f64),
+    c: (f64, f64),
+    d: (f64, f64),
+) -> bool {
+    if a.0.max(b.0) < c.0.min(d.0)
+        || c.0.max(d.0) < a.0.min(b.0)
+        || a.1.max(b.1) < c.1.min(d.1)
+        || c.1.max(d.1) < a.1.min(b.1)
+    {
+        return false;
+    }
+    let d1 = cross(c, d, a);
+    let d2 = cross(c, d, b);
+    let d3 = cross(a, b, c);
+    let d4 = cross(a, b, d);
+    if ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0))
+        && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0))
+    {
+        return true;
+    }
+    // touching or collinear cases, the bounding boxes already overlap
+    (d1 == 0.0 && on_segment(c, d, a))
+        || (d2 == 0.0 && on_segment(c, d, b))
+        || (d3 == 0.0 && on_segment(a, b, c))
+        || (d4 == 0.0 && on_segment(a, b, d))
+}
+
+fn on_segment(a: (f64, f64), b: (f64, f64), p: (f64, f64)) -> bool {
+    p.0 >= a.0.min(b.0) && p.0 <= a.0.max(b.0) && p.1 >= a.1.min(b.1) && p.1 <= a.1.max(b.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::maps::define_floor_patterns;
+    use crate::area_gen::test_helpers::generate;
+
+    const TILE_SIZE: i32 = 60;
+
+    fn shape(points: &[(f32, f32)], inner_if_true: bool) -> Shape {
+        Shape {
+            points: points.to_vec(),
+            inner_if_true,
+            parent: None,
+            children: Vec::new(),
+            depth: inner_if_true as usize,
+        }
+    }
+
+    fn copy(shapes: &[Shape]) -> Vec<Shape> {
+        shapes
+            .iter()
+            .map(|original| Shape {
+                parent: original.parent,
+                children: original.children.clone(),
+                depth: original.depth,
+                ..shape(&original.points, original.inner_if_true)
+            })
+            .collect()
+    }
+
+    fn area(shape: &Shape) -> f64 {
+        let points: Vec<(f64, f64)> = shape
+            .points
+            .iter()
+            .map(|p| (p.0 as f64, p.1 as f64))
+            .collect();
+        signed_area(&points).abs()
+    }
+
+    // stairs going down to the right, a tile per step, walkable inside
+    fn staircase() -> Vec<(f32, f32)> {
+        let t = TILE_SIZE as f32;
+        let mut points = vec![(0.0, 0.0)];
+        for step in 0..5 {
+            let step = step as f32;
+            points.push(((step + 1.0) * t, step * t));
+            points.push(((step + 1.0) * t, (step + 1.0) * t));
+        }
+        points.push((0.0, 5.0 * t));
+        points
+    }
+
+    const MODES: [PolygonSimplification; 5] = [
+        PolygonSimplification::MergeCollinear,
+        PolygonSimplification::DouglasPeucker(10.0),
+        PolygonSimplification::DouglasPeucker(30.0),
+        PolygonSimplification::DouglasPeucker(200.0),
+        PolygonSimplification::StaircaseToDiagonal,
+    ];
+
+    fn assert_walkable_area_kept(before: &[Shape], after: &[Shape], mode: PolygonSimplification) {
+        for (before, after) in before.iter().zip(after) {
+            if before.inner_if_true {
+                assert!(
+                    area(after) <= area(before) + 1e-3,
+                    "hole grew with {:?}",
+                    mode
+                );
+            } else {
+                assert!(
+                    area(after) >= area(before) - 1e-3,
+                    "shape shrank with {:?}",
+                    mode
+                );
+            }
+        }
+        assert_eq!(validate_shapes(after), Ok(()), "{:?}", mode);
+    }
+
+    // square from min to max, counter clockwise on screen
+    fn square(min: f32, max: f32) -> Vec<(f32, f32)> {
+        vec![(min, min), (min, max), (max, max), (max, min)]
+    }
+
+    #[test]
+    fn nested_shapes_alternate_holes_and_islands() {
+        // outer area, hole, island in the hole, hole in the island
+        let mut shapes: Vec<Shape> = [(200.0, 400.0), (0.0, 600.0), (250.0, 350.0), (100.0, 500.0)]
+            .iter()
+            .map(|(min, max)| shape(&square(*min, *max), false))
+            .collect();
+        build_hierarchy(&mut shapes);
+        assert_eq!(
+            shapes.iter().map(|shape| shape.depth).collect::<Vec<_>>(),
+            [2, 0, 3, 1]
+        );
+        assert_eq!(
+            shapes.iter().map(|shape| shape.parent).collect::<Vec<_>>(),
+            [Some(3), None, Some(0), Some(1)]
+        );
+        assert_eq!(shapes[1].children, [3]);
+        assert_eq!(
+            shapes
+                .iter()
+                .map(|shape| shape.inner_if_true)
+                .collect::<Vec<_>>(),
+            [false, false, true, true]
+        );
+        assert_eq!(validate_shapes(&shapes), Ok(()));
+    }
+
+    #[test]
+    fn islands_in_holes() {
+        // two holes in the outer area, one of them with an island
+        let mut shapes = vec![
+            shape(&square(0.0, 1000.0), true),
+            shape(&square(100.0, 400.0), false),
+            shape(&square(600.0, 900.0), false),
+            shape(&square(200.0, 300.0), true),
+        ];
+        build_hierarchy(&mut shapes);
+        assert_eq!(
+            shapes.iter().map(|shape| shape.parent).collect::<Vec<_>>(),
+            [None, Some(0), Some(0), Some(1)]
+        );
+        assert_eq!(
+            shapes
+                .iter()
+                .map(|shape| shape.inner_if_true)
+                .collect::<Vec<_>>(),
+            [false, true, true, false]
+        );
+        // walkable inside shapes are clockwise on screen, holes counter clockwise
+        for shape in &shapes {
+            let points: Vec<(f64, f64)> = shape
+                .points
+                .iter()
+                .map(|p| (p.0 as f64, p.1 as f64))
+                .collect();
+            assert_eq!(signed_area(&points) > 0.0, !shape.inner_if_true);
+        }
+        assert_eq!(validate_shapes(&shapes), Ok(()));
+    }
+
+    #[test]
+    fn invalid_shapes_are_reported() {
+        let mut shapes = vec![
+            shape(&square(0.0, 600.0), false),
+            shape(&square(100.0, 500.0), false),
+        ];
+        build_hierarchy(&mut shapes);
+        shapes[1].points.reverse();
+        assert_eq!(validate_shapes(&shapes), Err(ShapeError::WrongWinding(1)));
+
+        let bow_tie = [(0.0, 0.0), (100.0, 100.0), (100.0, 0.0), (0.0, 100.0)];
+        assert_eq!(
+            validate_shapes(&[shape(&bow_tie, false)]),
+            Err(ShapeError::SelfIntersection(0))
+        );
+
+        let mut overlapping = vec![
+            shape(&square(0.0, 100.0), false),
+            shape(&square(50.0, 150.0), false),
+        ];
+        build_hierarchy(&mut overlapping);
+        assert_eq!(
+            validate_shapes(&overlapping),
+            Err(ShapeError::ShapesIntersect(0, 1))
+        );
+
+        let line = [(0.0, 0.0), (100.0, 0.0)];
+        assert_eq!(
+            validate_shapes(&[shape(&line, false)]),
+            Err(ShapeError::TooFewPoints(0))
+        );
+    }
+
+    #[test]
+    fn none_keeps_every_corner() {
+        let mut points = staircase();
+        // a point in the middle of the bottom edge
+        points.insert(points.len() - 1, (TILE_SIZE as f32, 5.0 * TILE_SIZE as f32));
+        let simplified = simplify_polygon(&points, true, PolygonSimplification::None, TILE_SIZE);
+        assert_eq!(simplified, points);
+        let merged = simplify_polygon(
+            &points,
+            true,
+            PolygonSimplification::MergeCollinear,
+            TILE_SIZE,
+        );
+        assert_eq!(merged.len(), points.len() - 1);
+    }
+
+    #[test]
+    fn staircases_become_diagonals() {
+        let mut shapes = vec![shape(&staircase(), false)];
+        build_hierarchy(&mut shapes);
+        let before = copy(&shapes);
+        simplify_shapes(
+            &mut shapes,
+            PolygonSimplification::StaircaseToDiagonal,
+            TILE_SIZE,
+        );
+        assert!(shapes[0].points.len() < before[0].points.len());
+        assert_walkable_area_kept(&before, &shapes, PolygonSimplification::StaircaseToDiagonal);
+
+        // as a hole the staircase can only be cut inside
+        let mut holes = vec![
+            shape(
+                &[
+                    (-60.0, -60.0),
+                    (400.0, -60.0),
+                    (400.0, 400.0),
+                    (-60.0, 400.0),
+                ],
+                false,
+            ),
+            shape(&staircase(), true),
+        ];
+        build_hierarchy(&mut holes);
+        let before = copy(&holes);
+        for mode in MODES {
+            let mut after = copy(&before);
+            simplify_shapes(&mut after, mode, TILE_SIZE);
+            assert_walkable_area_kept(&before, &after, mode);
+        }
+    }
+
+    #[test]
+    fn simplified_edges_do_not_cross_other_shapes() {
+        let t = TILE_SIZE as f32;
+        let outer = [(-t, -t), (7.0 * t, -t), (7.0 * t, 7.0 * t), (-t, 7.0 * t)];
+        let rectangle = |min: (f32, f32), max: (f32, f32)| {
+            [
+                (min.0, min.1),
+                (max.0, min.1),
+                (max.0, max.1),
+                (min.0, max.1),
+            ]
+        };
+        // small islands in the hole, across the diagonal of the steps
+        // and in the corner of a step that the diagonal would cut off
+        let across = rectangle((1.65 * t, 1.55 * t), (1.95 * t, 1.9 * t));
+        let cut_off = rectangle((2.7 * t, 2.1 * t), (2.9 * t, 2.3 * t));
+        let mut shapes = vec![
+            shape(&outer, false),
+            shape(&staircase(), true),
+            shape(&across, false),
+            shape(&cut_off, false),
+        ];
+        build_hierarchy(&mut shapes);
+        let parents: Vec<Option<usize>> = shapes.iter().map(|shape| shape.parent).collect();
+        assert_eq!(parents, [None, Some(0), Some(1), Some(1)]);
+        let before = copy(&shapes);
+        for mode in MODES {
+            let mut after = copy(&before);
+            simplify_shapes(&mut after, mode, TILE_SIZE);
+            assert_walkable_area_kept(&before, &after, mode);
+            build_hierarchy(&mut after);
+            assert_eq!(
+                after.iter().map(|shape| shape.parent).collect::<Vec<_>>(),
+                parents,
+                "{:?}",
+                mode
+            );
+        }
+        // the steps next to the islands are kept, the others still become diagonals
+        let mut after = copy(&before);
+        simplify_shapes(
+            &mut after,
+            PolygonSimplification::StaircaseToDiagonal,
+            TILE_SIZE,
+        );
+        assert!(after[1].points.len() < before[1].points.len());
+    }
+
+    #[test]
+    fn generated_shapes_keep_their_walkable_area() {
+        for map_index in 0..define_floor_patterns().len() {
+            for seed in [1, 2] {
+                let traced = generate(map_index, seed).oob_polygons;
+                for mode in MODES {
+                    let mut simplified = copy(&traced);
+                    simplify_shapes(&mut simplified, mode, TILE_SIZE);
+                    assert_walkable_area_kept(&traced, &simplified, mode);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,235 @@
+// World space queries on the generation output, positions are in pixels
+use rand::Rng;
+
+use super::{px_to_tile, tile_center, AreaGenerationOutput};
+use crate::area_gen::maps::TileType;
+
+// One bit per tile, the index of tile (x, y) is x * height + y
+#[derive(Clone)]
+pub struct WalkableBitmap {
+    pub width: usize,
+    pub height: usize,
+    pub bits: Vec<u64>,
+    // number of walkable tiles
+    pub count: usize,
+}
+
+impl WalkableBitmap {
+    pub fn new(width: usize, height: usize) -> WalkableBitmap {
+        WalkableBitmap {
+            width,
+            height,
+            bits: vec![0; (width * height).div_ceil(64)],
+            count: 0,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        let index = x * self.height + y;
+        self.bits[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, walkable: bool) {
+        if self.get(x, y) == walkable {
+            return;
+        }
+        let index = x * self.height + y;
+        self.bits[index / 64] ^= 1 << (index % 64);
+        if walkable {
+            self.count += 1;
+        } else {
+            self.count -= 1;
+        }
+    }
+
+    // Coordinates of the nth walkable tile, in index order
+    pub fn nth_walkable(&self, mut n: usize) -> Option<(usize, usize)> {
+        for (word_index, word) in self.bits.iter().enumerate() {
+            let ones = word.count_ones() as usize;
+            if n >= ones {
+                n -= ones;
+                continue;
+            }
+            let mut word = *word;
+            for _ in 0..n {
+                // drop the lowest set bit
+                word &= word - 1;
+            }
+            let index = word_index * 64 + word.trailing_zeros() as usize;
+            return Some((index / self.height, index % self.height));
+        }
+        None
+    }
+}
+
+impl AreaGenerationOutput {
+    pub fn tile_at(&self, point: (f32, f32)) -> Option<(usize, usize)> {
+        let tile = px_to_tile(point, self.tile_size);
+        if tile.0 < 0 || tile.1 < 0 || tile.0 >= self.width as i32 || tile.1 >= self.height as i32 {
+            return None;
+        }
+        Some((tile.0 as usize, tile.1 as usize))
+    }
+
+    pub fn tile_type_at(&self, point: (f32, f32)) -> Option<TileType> {
+        self.tile_at(point)
+            .map(|tile| self.tiles[tile.0 * self.height as usize + tile.1])
+    }
+
+    pub fn is_walkable_at(&self, point: (f32, f32)) -> bool {
+        self.tile_at(point)
+            .is_some_and(|tile| self.walkable.get(tile.0, tile.1))
+    }
+
+    // The point itself when walkable, else the center of the closest walkable tile
+    pub fn nearest_walkable(&self, point: (f32, f32)) -> Option<(f32, f32)> {
+        if self.is_walkable_at(point) {
+            return Some(point);
+        }
+        let tile = px_to_tile(point, self.tile_size);
+        let tile_size = self.tile_size as f32;
+        nearest_walkable_tile(
+            tile,
+            (self.width as usize, self.height as usize),
+            |x, y| self.walkable.get(x, y),
+            |x, y| {
+                let center = tile_center((x, y), self.tile_size);
+                ((center.0 - point.0).powi(2) + (center.1 - point.1).powi(2)).sqrt() / tile_size
+            },
+        )
+        .map(|tile| tile_center(tile, self.tile_size))
+    }
+
+    // Uniform over the walkable tiles, then uniform inside the tile
+    pub fn random_walkable_point<R: Rng>(&self, rng: &mut R) -> Option<(f32, f32)> {
+        if self.walkable.count == 0 {
+            return None;
+        }
+        let tile = self
+            .walkable
+            .nth_walkable(rng.gen_range(0..self.walkable.count))?;
+        let center = tile_center(tile, self.tile_size);
+        let half_tile = self.tile_size as f32 / 2.0;
+        Some((
+            center.0 + rng.gen_range(-half_tile..half_tile),
+            center.1 + rng.gen_range(-half_tile..half_tile),
+        ))
+    }
+
+    // Tiles with their center within the radius, walkable or not
+    pub fn tiles_in_radius(&self, point: (f32, f32), radius: f32) -> Vec<(usize, usize)> {
+        let tile = px_to_tile(point, self.tile_size);
+        let tile_radius = (radius / self.tile_size as f32).ceil() as i32 + 1;
+        let mut tiles = Vec::new();
+        for x in (tile.0 - tile_radius).max(0)..=(tile.0 + tile_radius).min(self.width as i32 - 1) {
+            for y in
+                (tile.1 - tile_radius).max(0)..=(tile.1 + tile_radius).min(self.height as i32 - 1)
+            {
+                let center = tile_center((x as usize, y as usize), self.tile_size);
+                if (center.0 - point.0).powi(2) + (center.1 - point.1).powi(2) <= radius * radius {
+                    tiles.push((x as usize, y as usize));
+                }
+            }
+        }
+        tiles
+    }
+}
+
+// Walkable tile with the smallest distance, searched in growing squares around the tile.
+// The distance is in tiles, the squares further than the best tile found are not searched
+pub(crate) fn nearest_walkable_tile(
+    tile: (i32, i32),
+    size: (usize, usize),
+    is_walkable: impl Fn(usize, usize) -> bool,
+    distance: impl Fn(usize, usize) -> f32,
+) -> Option<(usize, usize)> {
+    // far enough to reach every tile, even from a tile outside of the grid
+    let max_radius = size.0.max(size.1) as i32 + tile.0.abs().max(tile.1.abs());
+    let mut nearest: Option<((usize, usize), f32)> = None;
+    for radius in 0..=max_radius {
+        if nearest.is_some_and(|(_, best)| (radius - 1) as f32 > best) {
+            break;
+        }
+        for (x, y) in ring(tile, radius) {
+            if x < 0 || y < 0 || x >= size.0 as i32 || y >= size.1 as i32 {
+                continue;
+            }
+            let (x, y) = (x as usize, y as usize);
+            if !is_walkable(x, y) {
+                continue;
+            }
+            let distance = distance(x, y);
+            if nearest.is_none_or(|(_, best)| distance < best) {
+                nearest = Some(((x, y), distance));
+            }
+        }
+    }
+    nearest.map(|(tile, _)| tile)
+}
+
+// Tiles on the border of the square of the given radius around the center tile
+fn ring(center: (i32, i32), radius: i32) -> impl Iterator<Item = (i32, i32)> {
+    let (x0, y0) = center;
+    let rows = (x0 - radius..=x0 + radius)
+        .flat_map(move |x| [(x, y0 - radius), (x, y0 + radius)])
+        // the center tile alone for a radius of 0
+        .take(if radius == 0 { 1 } else { usize::MAX });
+    let columns =
+        (y0 - radius + 1..y0 + radius).flat_map(move |y| [(x0 - radius, y), (x0 + radius, y)]);
+    rows.chain(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::test_helpers::generate;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn rings_are_square_borders() {
+        assert_eq!(ring((3, 4), 0).collect::<Vec<_>>(), [(3, 4)]);
+        for radius in 1..5 {
+            let mut tiles: Vec<(i32, i32)> = ring((0, 0), radius).collect();
+            tiles.sort();
+            tiles.dedup();
+            assert_eq!(tiles.len(), 8 * radius as usize);
+            assert!(tiles
+                .iter()
+                .all(|tile| tile.0.abs().max(tile.1.abs()) == radius));
+        }
+    }
+
+    #[test]
+    fn nearest_walkable_matches_a_full_scan() {
+        let area = generate(0, 3);
+        let walkable: Vec<(usize, usize)> = (0..area.walkable.count)
+            .filter_map(|n| area.walkable.nth_walkable(n))
+            .collect();
+        let size = (
+            (area.width as i32 * area.tile_size) as f32,
+            (area.height as i32 * area.tile_size) as f32,
+        );
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for _ in 0..200 {
+            // points around the area too
+            let point = (
+                rng.gen_range(-0.2 * size.0..1.2 * size.0),
+                rng.gen_range(-0.2 * size.1..1.2 * size.1),
+            );
+            let nearest = area.nearest_walkable(point).unwrap();
+            if area.is_walkable_at(point) {
+                assert_eq!(nearest, point);
+                continue;
+            }
+            let distance = |p: (f32, f32)| (p.0 - point.0).powi(2) + (p.1 - point.1).powi(2);
+            let best = walkable
+                .iter()
+                .map(|tile| distance(tile_center(*tile, area.tile_size)))
+                .min_by(f32::total_cmp)
+                .unwrap();
+            assert!(area.is_walkable_at(nearest));
+            assert!(distance(nearest) <= best * (1.0 + 1e-6));
+        }
+    }
+}
@@ -0,0 +1,419 @@
+// Seed search: generate many seeds of a map and keep the ones matching constraints
+use std::sync::Mutex;
+use std::thread;
+
+use super::connectors::Connector;
+use super::maps::TileType;
+use super::mobs::Rarity;
+use super::pathfinding::{self, PathOptions};
+use super::tiers::AreaModifier;
+use super::{
+    generate_area, px_to_tile, AreaGenerationOutput, GenerationConfig, GenerationRequest, Rerolls,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Metric {
+    // holes in the walkable area
+    Holes,
+    // walking distance in tiles from the spawn to the boss
+    CriticalPath,
+    Packs,
+    Enemies,
+    Rares,
+    Uniques,
+    WalkableTiles,
+    Width,
+    Height,
+    // walking distance in tiles from the spawn to the closest pack
+    NearestPack,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+// e.g. holes >= 3
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Constraint {
+    pub metric: Metric,
+    pub comparison: Comparison,
+    pub value: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AreaMetrics {
+    pub holes: usize,
+    // none when the boss can not be reached
+    pub critical_path: Option<f32>,
+    pub packs: usize,
+    pub enemies: usize,
+    pub rares: usize,
+    pub uniques: usize,
+    pub walkable_tiles: usize,
+    pub width: u32,
+    pub height: u32,
+    pub nearest_pack: Option<f32>,
+}
+
+#[derive(Clone)]
+pub struct SearchRequest {
+    pub map_index: usize,
+    pub tier: u32,
+    pub modifiers: Vec<AreaModifier>,
+    pub entry: Option<Connector>,
+    pub exit: Option<Connector>,
+    // seeds first_seed to first_seed + count - 1 are tried
+    pub first_seed: u64,
+    pub count: u64,
+    pub threads: usize,
+    // stops once that many seeds match, the matches are the lowest matching seeds whatever
+    // the number of threads
+    pub max_matches: Option<usize>,
+    // all of them must hold
+    pub constraints: Vec<Constraint>,
+}
+
+pub struct SearchMatch {
+    pub request: GenerationRequest,
+    pub metrics: AreaMetrics,
+}
+
+impl Metric {
+    pub fn from_name(name: &str) -> Option<Metric> {
+        Some(match name {
+            "holes" => Metric::Holes,
+            "critical_path" => Metric::CriticalPath,
+            "packs" => Metric::Packs,
+            "enemies" => Metric::Enemies,
+            "rares" => Metric::Rares,
+            "uniques" => Metric::Uniques,
+            "walkable_tiles" => Metric::WalkableTiles,
+            "width" => Metric::Width,
+            "height" => Metric::Height,
+            "nearest_pack" => Metric::NearestPack,
+            _ => return None,
+        })
+    }
+}
+
+impl Constraint {
+    // <metric><comparison><value> with one of < <= == != >= >, e.g. "critical_path>400"
+    pub fn parse(text: &str) -> Option<Constraint> {
+        // two characters operators first, > would also match >=
+        let operators = [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("==", Comparison::Equal),
+            ("!=", Comparison::NotEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ];
+        let (operator, comparison) = operators
+            .into_iter()
+            .find(|(operator, _)| text.contains(operator))?;
+        let (metric, value) = text.split_once(operator)?;
+        Some(Constraint {
+            metric: Metric::from_name(metric.trim())?,
+            comparison,
+            value: value.trim().parse().ok()?,
+        })
+    }
+
+    // metrics that could not be measured match nothing
+    pub fn matches(&self, metrics: &AreaMetrics) -> bool {
+        metrics
+            .get(self.metric)
+            .is_some_and(|value| match self.comparison {
+                Comparison::Less => value < self.value,
+                Comparison::LessOrEqual => value <= self.value,
+                Comparison::Equal => value == self.value,
+                Comparison::NotEqual => value != self.value,
+                Comparison::GreaterOrEqual => value >= self.value,
+                Comparison::Greater => value > self.value,
+            })
+    }
+}
+
+impl AreaMetrics {
+    pub fn from_output(area: &AreaGenerationOutput) -> AreaMetrics {
+        let height = area.height as usize;
+        let spawn = px_to_tile(
+            (
+                area.player_spawn_position.0 as f32,
+                area.player_spawn_position.1 as f32,
+            ),
+            area.tile_size,
+        );
+        let boss = area
+            .tiles
+            .iter()
+            .rposition(|tile| *tile == TileType::Boss)
+            .map(|index| (index / height, index % height));
+        let critical_path = boss.and_then(|boss| {
+            pathfinding::find_path(
+                area,
+                (spawn.0 as usize, spawn.1 as usize),
+                boss,
+                &PathOptions::default(),
+            )
+            .map(|path| path.cost)
+        });
+        let count_rarity = |rarity: Rarity| {
+            area.enemies
+                .iter()
+                .filter(|enemy| enemy.rarity == rarity)
+                .count()
+        };
+        AreaMetrics {
+            holes: area
+                .oob_polygons
+                .iter()
+                .filter(|shape| shape.inner_if_true)
+                .count(),
+            critical_path,
+            packs: area.packs.len(),
+            enemies: area.enemies.len(),
+            rares: count_rarity(Rarity::Rare),
+            uniques: count_rarity(Rarity::Unique),
+            walkable_tiles: area.walkable_x.len(),
+            width: area.width,
+            height: area.height,
+            nearest_pack: area.nearest_pack_distance,
+        }
+    }
+
+    pub fn get(&self, metric: Metric) -> Option<f64> {
+        Some(match metric {
+            Metric::Holes => self.holes as f64,
+            Metric::CriticalPath => self.critical_path? as f64,
+            Metric::Packs => self.packs as f64,
+            Metric::Enemies => self.enemies as f64,
+            Metric::Rares => self.rares as f64,
+            Metric::Uniques => self.uniques as f64,
+            Metric::WalkableTiles => self.walkable_tiles as f64,
+            Metric::Width => self.width as f64,
+            Metric::Height => self.height as f64,
+            Metric::NearestPack => self.nearest_pack? as f64,
+        })
+    }
+}
+
+// Matching seeds in increasing order, from first_seed
+pub fn search_seeds(request: &SearchRequest) -> Vec<SearchMatch> {
+    let config = GenerationConfig::headless();
+    let threads = request.threads.max(1) as u64;
+    // indexes of the seeds that matched so far, in any order
+    let found: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+    // a thread only stops once enough lower seeds matched, so the result does not depend on
+    // how far ahead the other threads are
+    let enough = |found: &Mutex<Vec<u64>>, index: u64| {
+        request.max_matches.is_some_and(|max| {
+            let found = found.lock().unwrap();
+            found.iter().filter(|other| **other < index).count() >= max
+        })
+    };
+    let mut matches: Vec<SearchMatch> = thread::scope(|scope| {
+        let handlers: Vec<_> = (0..threads)
+            .map(|thread_index| {
+                let (config, found) = (&config, &found);
+                scope.spawn(move || {
+                    let mut matches = Vec::new();
+                    // seeds are dealt to the threads in turn
+                    for index in (thread_index..request.count).step_by(threads as usize) {
+                        if enough(found, index) {
+                            break;
+                        }
+                        let generation = GenerationRequest {
+                            map_index: request.map_index,
+                            seed: request.first_seed.wrapping_add(index),
+                            tier: request.tier,
+                            modifiers: request.modifiers.clone(),
+                            entry: request.entry,
+                            exit: request.exit,
+                            side_gates: Vec::new(),
+                            rerolls: Rerolls::default(),
+                        };
+                        let metrics = AreaMetrics::from_output(&generate_area(&generation, config));
+                        if request
+                            .constraints
+                            .iter()
+                            .all(|constraint| constraint.matches(&metrics))
+                        {
+                            found.lock().unwrap().push(index);
+                            matches.push(SearchMatch {
+                                request: generation,
+                                metrics,
+                            });
+                        }
+                    }
+                    matches
+                })
+            })
+            .collect();
+        handlers
+            .into_iter()
+            .flat_map(|handler| handler.join().unwrap())
+            .collect()
+    });
+
+    // the seeds can wrap around
+    matches.sort_by_key(|found| found.request.seed.wrapping_sub(request.first_seed));
+    if let Some(max) = request.max_matches {
+        matches.truncate(max);
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::test_helpers::generate;
+
+    fn metrics() -> AreaMetrics {
+        AreaMetrics {
+            holes: 3,
+            critical_path: None,
+            packs: 10,
+            enemies: 40,
+            rares: 2,
+            uniques: 0,
+            walkable_tiles: 500,
+            width: 30,
+            height: 20,
+            nearest_pack: Some(12.5),
+        }
+    }
+
+    #[test]
+    fn two_character_operators_are_parsed_first() {
+        let parsed = |text: &str| {
+            let constraint = Constraint::parse(text).unwrap();
+            (constraint.metric, constraint.comparison, constraint.value)
+        };
+        assert_eq!(
+            parsed("holes>=3"),
+            (Metric::Holes, Comparison::GreaterOrEqual, 3.0)
+        );
+        assert_eq!(parsed("holes>3"), (Metric::Holes, Comparison::Greater, 3.0));
+        assert_eq!(
+            parsed(" packs <= 400 "),
+            (Metric::Packs, Comparison::LessOrEqual, 400.0)
+        );
+        assert_eq!(
+            parsed("packs<400"),
+            (Metric::Packs, Comparison::Less, 400.0)
+        );
+        assert_eq!(
+            parsed("uniques!=0"),
+            (Metric::Uniques, Comparison::NotEqual, 0.0)
+        );
+        assert_eq!(
+            parsed("nearest_pack==12.5"),
+            (Metric::NearestPack, Comparison::Equal, 12.5)
+        );
+    }
+
+    #[test]
+    fn invalid_constraints_are_rejected() {
+        for text in ["lakes>3", "holes", "holes>", "holes>three", ">3", "holes=3"] {
+            assert_eq!(Constraint::parse(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn unmeasured_metrics_match_nothing() {
+        let metrics = metrics();
+        for text in ["critical_path>0", "critical_path<0", "critical_path!=1"] {
+            assert!(
+                !Constraint::parse(text).unwrap().matches(&metrics),
+                "{}",
+                text
+            );
+        }
+        for (text, expected) in [
+            ("holes>=3", true),
+            ("holes>3", false),
+            ("nearest_pack<13", true),
+            ("rares==2", true),
+        ] {
+            assert_eq!(
+                Constraint::parse(text).unwrap().matches(&metrics),
+                expected,
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn metrics_are_measured_on_the_output() {
+        for map_index in 0..5 {
+            let area = generate(map_index, 1);
+            let metrics = AreaMetrics::from_output(&area);
+            let holes = area
+                .oob_polygons
+                .iter()
+                .filter(|shape| shape.inner_if_true)
+                .count();
+            assert_eq!(metrics.holes, holes);
+            assert_eq!(metrics.packs, area.packs.len());
+            assert_eq!(metrics.enemies, area.enemies.len());
+            assert!(metrics.rares + metrics.uniques <= metrics.packs);
+            assert_eq!(metrics.walkable_tiles, area.walkable_y.len());
+            assert_eq!((metrics.width, metrics.height), (area.width, area.height));
+            assert_eq!(metrics.nearest_pack, area.nearest_pack_distance);
+            // the path is at least as long as the straight line from the spawn to the boss
+            let height = area.height as usize;
+            let boss = area
+                .tiles
+                .iter()
+                .position(|tile| *tile == TileType::Boss)
+                .map(|index| (index / height, index % height))
+                .unwrap();
+            let spawn = px_to_tile(
+                (
+                    area.player_spawn_position.0 as f32,
+                    area.player_spawn_position.1 as f32,
+                ),
+                area.tile_size,
+            );
+            let straight = ((boss.0 as f32 - spawn.0 as f32).powi(2)
+                + (boss.1 as f32 - spawn.1 as f32).powi(2))
+            .sqrt();
+            let critical_path = metrics.critical_path.unwrap();
+            assert!(critical_path + 1.0 >= straight, "map {}", map_index);
+        }
+    }
+
+    #[test]
+    fn max_matches_are_the_lowest_matching_seeds() {
+        let request = |threads: usize, max_matches: Option<usize>| SearchRequest {
+            map_index: 0,
+            tier: 1,
+            modifiers: Vec::new(),
+            entry: None,
+            exit: None,
+            first_seed: u64::MAX - 10,
+            count: 24,
+            threads,
+            max_matches,
+            constraints: vec![Constraint::parse("holes>=3").unwrap()],
+        };
+        let seeds = |matches: Vec<SearchMatch>| -> Vec<u64> {
+            matches.iter().map(|found| found.request.seed).collect()
+        };
+        let all = seeds(search_seeds(&request(1, None)));
+        // some of the lowest matches are past the wrap around of the seeds
+        assert!(all.len() > 5 && all[4] < 10, "{:?}", all);
+        assert_eq!(seeds(search_seeds(&request(3, None))), all);
+        for threads in [1, 2, 5] {
+            assert_eq!(seeds(search_seeds(&request(threads, Some(5)))), all[..5]);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+// Short text codes of generation requests, for the players to share areas
+//
+// Layout of the bytes before the text encoding, varints are LEB128:
+//   generator version varint
+//   map name (varint length + utf8), seed u64, tier varint
+//   modifiers count, then for each: modifier u8, value as a zigzag varint
+//   flags u8: bit 0 entry, bit 1 exit, bit 2 side gates, bit 3 rerolls
+//   entry and exit when flagged: side u8, offset f32
+//   side gates when flagged: count, then for each: side u8, offset f32
+//   rerolls when flagged: layout, landmarks, packs, mobs varints
+//   checksum: low 16 bits of the crc32 of everything before it
+//
+// The bytes are written in Crockford base32 in groups of 4 characters, e.g. 2G0C-M8T5-...
+use super::connectors::Connector;
+use super::maps::define_floor_patterns;
+use super::serialization::{
+    area_modifier_code, area_modifier_from_code, crc32, side_code, side_from_code, write_varint,
+    DecodeError, Reader,
+};
+use super::{GenerationRequest, Rerolls, GENERATOR_VERSION};
+
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const GROUP_SIZE: usize = 4;
+
+// Panics when the map index is not one of define_floor_patterns, like generate_area
+pub fn encode_seed_code(request: &GenerationRequest) -> String {
+    let map_name = define_floor_patterns()[request.map_index].name.clone();
+    let mut bytes = Vec::new();
+    write_varint(&mut bytes, GENERATOR_VERSION as u64);
+    write_varint(&mut bytes, map_name.len() as u64);
+    bytes.extend_from_slice(map_name.as_bytes());
+    bytes.extend_from_slice(&request.seed.to_le_bytes());
+    write_varint(&mut bytes, request.tier as u64);
+    write_varint(&mut bytes, request.modifiers.len() as u64);
+    for modifier in &request.modifiers {
+        let (code, value) = area_modifier_code(*modifier);
+        bytes.push(code);
+        // zigzag, small negative values stay short
+        write_varint(&mut bytes, ((value << 1) ^ (value >> 31)) as u32 as u64);
+    }
+
+    let has_rerolls = request.rerolls != Rerolls::default();
+    let flags = request.entry.is_some() as u8
+        | (request.exit.is_some() as u8) << 1
+        | (!request.side_gates.is_empty() as u8) << 2
+        | (has_rerolls as u8) << 3;
+    bytes.push(flags);
+    for connector in request.entry.iter().chain(&request.exit) {
+        write_connector(&mut bytes, connector);
+    }
+    if !request.side_gates.is_empty() {
+        write_varint(&mut bytes, request.side_gates.len() as u64);
+        for side_gate in &request.side_gates {
+            write_connector(&mut bytes, side_gate);
+        }
+    }
+    if has_rerolls {
+        let rerolls = request.rerolls;
+        for reroll in [
+            rerolls.layout,
+            rerolls.landmarks,
+            rerolls.packs,
+            rerolls.mobs,
+        ] {
+            write_varint(&mut bytes, reroll as u64);
+        }
+    }
+    let checksum = crc32(&bytes) as u16;
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+
+    let characters = to_base32(&bytes);
+    characters
+        .chunks(GROUP_SIZE)
+        .map(|group| String::from_utf8(group.to_vec()).unwrap())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// Case and dashes are ignored, O reads as 0 and I or L as 1
+pub fn decode_seed_code(code: &str) -> Result<GenerationRequest, DecodeError> {
+    let mut values = Vec::new();
+    for character in code.chars() {
+        let character = match character.to_ascii_uppercase() {
+            '-' | ' ' => continue,
+            'O' => '0',
+            'I' | 'L' => '1',
+            character => character,
+        };
+        let value = ALPHABET
+            .iter()
+            .position(|letter| *letter as char == character)
+            .ok_or(DecodeError::Invalid("not a seed code character"))?;
+        values.push(value as u8);
+    }
+    let bytes = from_base32(&values);
+    if bytes.len() < 2 {
+        return Err(DecodeError::Truncated);
+    }
+    let (content, checksum) = bytes.split_at(bytes.len() - 2);
+    if crc32(content) as u16 != u16::from_le_bytes([checksum[0], checksum[1]]) {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+    let mut reader = Reader {
+        bytes: content,
+        position: 0,
+    };
+
+    let version =
+        u16::try_from(reader.varint()?).map_err(|_| DecodeError::Invalid("varint is too big"))?;
+    if version != GENERATOR_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let name_length = reader.varint()? as usize;
+    let map_name = std::str::from_utf8(reader.take(name_length)?)
+        .map_err(|_| DecodeError::Invalid("map name is not utf8"))?;
+    let map_index = define_floor_patterns()
+        .iter()
+        .position(|map| map.name == map_name)
+        .ok_or(DecodeError::Invalid("unknown map"))?;
+    let seed = u64::from_le_bytes(reader.array()?);
+    let tier = reader.varint_u32()?;
+    let modifier_count = reader.varint()? as usize;
+    let mut modifiers = Vec::new();
+    for _ in 0..modifier_count {
+        let code = reader.byte()?;
+        let zigzag = reader.varint_u32()?;
+        let value = (zigzag >> 1) as i32 ^ -((zigzag & 1) as i32);
+        modifiers.push(area_modifier_from_code(code, value)?);
+    }
+
+    let flags = reader.byte()?;
+    let entry = if flags & 1 != 0 {
+        Some(read_connector(&mut reader)?)
+    } else {
+        None
+    };
+    let exit = if flags & 2 != 0 {
+        Some(read_connector(&mut reader)?)
+    } else {
+        None
+    };
+    let mut side_gates = Vec::new();
+    if flags & 4 != 0 {
+        for _ in 0..reader.varint()? {
+            side_gates.push(read_connector(&mut reader)?);
+        }
+    }
+    let rerolls = if flags & 8 != 0 {
+        Rerolls {
+            layout: reader.varint_u32()?,
+            landmarks: reader.varint_u32()?,
+            packs: reader.varint_u32()?,
+            mobs: reader.varint_u32()?,
+        }
+    } else {
+        Rerolls::default()
+    };
+    if reader.position != content.len() {
+        return Err(DecodeError::Invalid("unexpected data in the seed code"));
+    }
+
+    Ok(GenerationRequest {
+        map_index,
+        seed,
+        tier,
+        modifiers,
+        entry,
+        exit,
+        side_gates,
+        rerolls,
+    })
+}
+
+fn write_connector(bytes: &mut Vec<u8>, connector: &Connector) {
+    bytes.push(side_code(connector.side));
+    bytes.extend_from_slice(&connector.offset.to_le_bytes());
+}
+
+fn read_connector(reader: &mut Reader) -> Result<Connector, DecodeError> {
+    Ok(Connector {
+        side: side_from_code(reader.byte()?)?,
+        offset: f32::from_le_bytes(reader.array()?),
+    })
+}
+
+// 5 bits per character, the last one padded with zeros
+fn to_base32(bytes: &[u8]) -> Vec<u8> {
+    let mut characters = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            characters.push(ALPHABET[((buffer >> bits) & 31) as usize]);
+        }
+    }
+    if bits > 0 {
+        characters.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize]);
+    }
+    characters
+}
+
+// the padding bits of the last character are dropped
+fn from_base32(values: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for value in values {
+        buffer = (buffer << 5) | *value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::connectors::Side;
+    use crate::area_gen::tiers::AreaModifier;
+
+    fn request() -> GenerationRequest {
+        GenerationRequest {
+            tier: 12,
+            modifiers: vec![
+                AreaModifier::PackSize(30),
+                AreaModifier::RareChance(-20),
+                AreaModifier::ExtraMonsterModifiers(1),
+                AreaModifier::NoPacks,
+            ],
+            entry: Some(Connector {
+                side: Side::West,
+                offset: 0.25,
+            }),
+            exit: Some(Connector {
+                side: Side::East,
+                offset: 0.75,
+            }),
+            side_gates: vec![Connector {
+                side: Side::North,
+                offset: 0.6,
+            }],
+            rerolls: Rerolls {
+                layout: 0,
+                landmarks: 1,
+                packs: 2,
+                mobs: 300,
+            },
+            ..GenerationRequest::new(3, 0xdead_beef_0123_4567)
+        }
+    }
+
+    fn assert_same(decoded: &GenerationRequest, request: &GenerationRequest) {
+        assert_eq!(decoded.map_index, request.map_index);
+        assert_eq!(decoded.seed, request.seed);
+        assert_eq!(decoded.tier, request.tier);
+        assert_eq!(decoded.modifiers, request.modifiers);
+        assert_eq!(decoded.entry, request.entry);
+        assert_eq!(decoded.exit, request.exit);
+        assert_eq!(decoded.side_gates, request.side_gates);
+        assert_eq!(decoded.rerolls, request.rerolls);
+    }
+
+    #[test]
+    fn decoding_gives_back_the_request() {
+        let plain = GenerationRequest::new(0, 42);
+        for request in [request(), plain] {
+            let code = encode_seed_code(&request);
+            let decoded = decode_seed_code(&code).unwrap();
+            assert_same(&decoded, &request);
+            assert_eq!(encode_seed_code(&decoded), code);
+        }
+    }
+
+    #[test]
+    fn case_dashes_and_look_alike_letters_are_ignored() {
+        let request = request();
+        let code = encode_seed_code(&request);
+        let variants = [
+            code.to_lowercase(),
+            code.replace('-', ""),
+            code.replace('-', " "),
+            code.replace('0', "O").replace('1', "I"),
+            code.replace('0', "o").replace('1', "l"),
+        ];
+        for variant in variants {
+            assert_same(&decode_seed_code(&variant).unwrap(), &request);
+        }
+    }
+
+    #[test]
+    fn changed_characters_fail_the_checksum() {
+        let code = encode_seed_code(&request());
+        // characters of the first three groups, not the dashes
+        for index in [0, 6, 12] {
+            let mut changed = code.clone().into_bytes();
+            changed[index] = if changed[index] == b'7' { b'8' } else { b'7' };
+            let changed = String::from_utf8(changed).unwrap();
+            assert_eq!(
+                decode_seed_code(&changed).err(),
+                Some(DecodeError::ChecksumMismatch),
+                "{}",
+                changed
+            );
+        }
+        assert_eq!(
+            decode_seed_code("042M-U").err(),
+            Some(DecodeError::Invalid("not a seed code character"))
+        );
+    }
+
+    fn code_with_checksum(mut bytes: Vec<u8>) -> String {
+        let checksum = crc32(&bytes) as u16;
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        String::from_utf8(to_base32(&bytes)).unwrap()
+    }
+
+    #[test]
+    fn other_generator_versions_are_rejected() {
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, GENERATOR_VERSION as u64 + 1);
+        bytes.extend_from_slice(&[0; 12]);
+        assert_eq!(
+            decode_seed_code(&code_with_checksum(bytes)).err(),
+            Some(DecodeError::UnsupportedVersion(GENERATOR_VERSION + 1))
+        );
+        // not read back as the current version once narrowed
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, GENERATOR_VERSION as u64 + (1 << 16));
+        bytes.extend_from_slice(&[0; 12]);
+        assert_eq!(
+            decode_seed_code(&code_with_checksum(bytes)).err(),
+            Some(DecodeError::Invalid("varint is too big"))
+        );
+    }
+
+    #[test]
+    fn oversized_tiers_are_rejected() {
+        let name = &define_floor_patterns()[0].name;
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, GENERATOR_VERSION as u64);
+        write_varint(&mut bytes, name.len() as u64);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&7u64.to_le_bytes());
+        write_varint(&mut bytes, 1 << 32);
+        // modifiers, flags
+        bytes.extend_from_slice(&[0; 2]);
+        assert_eq!(
+            decode_seed_code(&code_with_checksum(bytes)).err(),
+            Some(DecodeError::Invalid("varint is too big"))
+        );
+    }
+}
@@ -0,0 +1,876 @@
+// Compact binary format for generated areas, to send them from the server to the clients
+//
+// Layout, little endian, varints are LEB128:
+//   magic "AGEN", version u16
+//   metadata: seed u64, map name (varint length + utf8), widened tiles varint, tier varint,
+//     modifiers count, then for each: modifier u8, value i32,
+//     entry and exit connectors: u8 0 for none, else side code + 1 then offset f32,
+//     side gates count, then for each: side code u8, offset f32,
+//     rerolls of the layout, landmarks, packs and mobs varints
+//   tile size varint, mob size varint
+//   width varint, height varint, the area in pixels fits in an i32
+//   tiles: runs of (tile code u8, walkable in the high bit) + varint run length, x major
+//   player spawn: i32, i32
+//   polygons: count, then for each: flags u8 (bit 0 inner), parent varint (0 for none,
+//     else index + 1), depth varint, point count varint, points as f32 pairs
+//   packs: count, then for each: tile x varint, tile y varint, difficulty f32
+//   monster ids: count, then each id as varint length + utf8
+//   enemies: count, then for each: x varint, y varint, pack index varint,
+//     monster id index varint, rarity u8,
+//     modifier count varint, modifiers u8
+//   landmarks: count, then for each: kind u8, tile x varint, tile y varint
+//   checksum: crc32 of everything before it
+//
+// The navmesh and colliders are not part of the format, they can be rebuilt from the tiles.
+// The nearest pack distance is only computed for QA during the generation.
+// The gates only depend on the connectors and the size, they are rebuilt too.
+use super::{tile_center, AreaGenerationOutput, AreaMetadata, Enemy, Pack, Rerolls, Shape};
+use crate::area_gen::connectors::{self, Connector, Side};
+use crate::area_gen::landmarks::{Landmark, LandmarkKind};
+use crate::area_gen::maps::TileType;
+use crate::area_gen::mobs::{MonsterModifier, Rarity};
+use crate::area_gen::query::WalkableBitmap;
+use crate::area_gen::tiers::AreaModifier;
+
+const MAGIC: &[u8; 4] = b"AGEN";
+// Bumped once per release that changes the layout above, not once per change, the clients
+// are shipped with the server so only the version of the current release is decoded.
+// 1: first release
+pub const FORMAT_VERSION: u16 = 1;
+// an area bigger than this is not something the generator makes, whatever the bytes say
+const MAX_TILES: usize = 1 << 24;
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    ChecksumMismatch,
+    // the data is well formed but describes something impossible
+    Invalid(&'static str),
+}
+
+pub fn encode_area(area: &AreaGenerationOutput) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+
+    bytes.extend_from_slice(&area.metadata.seed.to_le_bytes());
+    write_varint(&mut bytes, area.metadata.map_name.len() as u64);
+    bytes.extend_from_slice(area.metadata.map_name.as_bytes());
+    write_varint(&mut bytes, area.widened_tiles as u64);
+    write_varint(&mut bytes, area.metadata.tier as u64);
+    write_varint(&mut bytes, area.metadata.modifiers.len() as u64);
+    for modifier in &area.metadata.modifiers {
+        let (code, value) = area_modifier_code(*modifier);
+        bytes.push(code);
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    for connector in [area.metadata.entry, area.metadata.exit] {
+        match connector {
+            Some(connector) => {
+                bytes.push(side_code(connector.side) + 1);
+                bytes.extend_from_slice(&connector.offset.to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+    }
+    write_varint(&mut bytes, area.metadata.side_gates.len() as u64);
+    for side_gate in &area.metadata.side_gates {
+        bytes.push(side_code(side_gate.side));
+        bytes.extend_from_slice(&side_gate.offset.to_le_bytes());
+    }
+    let rerolls = area.metadata.rerolls;
+    for reroll in [
+        rerolls.layout,
+        rerolls.landmarks,
+        rerolls.packs,
+        rerolls.mobs,
+    ] {
+        write_varint(&mut bytes, reroll as u64);
+    }
+    write_varint(&mut bytes, area.tile_size as u64);
+    write_varint(&mut bytes, area.mob_size as u64);
+
+    write_varint(&mut bytes, area.width as u64);
+    write_varint(&mut bytes, area.height as u64);
+    let height = area.height as usize;
+    let mut run: Option<(u8, u64)> = None;
+    for (index, tile_type) in area.tiles.iter().enumerate() {
+        let walkable = area.walkable.get(index / height, index % height);
+        let value = tile_code(*tile_type) | if walkable { 0x80 } else { 0 };
+        run = match run {
+            Some((current, length)) if current == value => Some((current, length + 1)),
+            Some((current, length)) => {
+                bytes.push(current);
+                write_varint(&mut bytes, length);
+                Some((value, 1))
+            }
+            None => Some((value, 1)),
+        };
+    }
+    if let Some((current, length)) = run {
+        bytes.push(current);
+        write_varint(&mut bytes, length);
+    }
+
+    bytes.extend_from_slice(&area.player_spawn_position.0.to_le_bytes());
+    bytes.extend_from_slice(&area.player_spawn_position.1.to_le_bytes());
+
+    write_varint(&mut bytes, area.oob_polygons.len() as u64);
+    for shape in &area.oob_polygons {
+        bytes.push(shape.inner_if_true as u8);
+        write_varint(
+            &mut bytes,
+            shape.parent.map_or(0, |parent| parent as u64 + 1),
+        );
+        write_varint(&mut bytes, shape.depth as u64);
+        write_varint(&mut bytes, shape.points.len() as u64);
+        for point in &shape.points {
+            bytes.extend_from_slice(&point.0.to_le_bytes());
+            bytes.extend_from_slice(&point.1.to_le_bytes());
+        }
+    }
+
+    write_varint(&mut bytes, area.packs.len() as u64);
+    for pack in &area.packs {
+        write_varint(&mut bytes, pack.tile.0 as u64);
+        write_varint(&mut bytes, pack.tile.1 as u64);
+        bytes.extend_from_slice(&pack.difficulty.to_le_bytes());
+    }
+
+    // each monster id is written once, enemies refer to it by index
+    let mut monster_ids: Vec<&str> = Vec::new();
+    for enemy in &area.enemies {
+        if !monster_ids.contains(&enemy.monster_id.as_str()) {
+            monster_ids.push(&enemy.monster_id);
+        }
+    }
+    write_varint(&mut bytes, monster_ids.len() as u64);
+    for monster_id in &monster_ids {
+        write_varint(&mut bytes, monster_id.len() as u64);
+        bytes.extend_from_slice(monster_id.as_bytes());
+    }
+    write_varint(&mut bytes, area.enemies.len() as u64);
+    for enemy in &area.enemies {
+        write_varint(&mut bytes, enemy.point.0 as u64);
+        write_varint(&mut bytes, enemy.point.1 as u64);
+        write_varint(&mut bytes, enemy.pack as u64);
+        let id_index = monster_ids
+            .iter()
+            .position(|monster_id| *monster_id == enemy.monster_id)
+            .unwrap();
+        write_varint(&mut bytes, id_index as u64);
+        bytes.push(rarity_code(enemy.rarity));
+        write_varint(&mut bytes, enemy.modifiers.len() as u64);
+        for modifier in &enemy.modifiers {
+            bytes.push(modifier_code(*modifier));
+        }
+    }
+
+    write_varint(&mut bytes, area.landmarks.len() as u64);
+    for landmark in &area.landmarks {
+        bytes.push(landmark_code(landmark.kind));
+        write_varint(&mut bytes, landmark.tile.0 as u64);
+        write_varint(&mut bytes, landmark.tile.1 as u64);
+    }
+
+    let checksum = crc32(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+    bytes
+}
+
+pub fn decode_area(bytes: &[u8]) -> Result<AreaGenerationOutput, DecodeError> {
+    if bytes.len() < MAGIC.len() + 2 + 4 {
+        return Err(DecodeError::Truncated);
+    }
+    if &bytes[..4] != MAGIC {
+        return Err(DecodeError::BadMagic);
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != FORMAT_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let (content, checksum) = bytes.split_at(bytes.len() - 4);
+    if crc32(content) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+    let mut reader = Reader {
+        bytes: content,
+        position: 6,
+    };
+
+    let seed = u64::from_le_bytes(reader.array()?);
+    let name_length = reader.varint()? as usize;
+    let map_name = String::from_utf8(reader.take(name_length)?.to_vec())
+        .map_err(|_| DecodeError::Invalid("map name is not utf8"))?;
+    let widened_tiles = reader.varint()? as usize;
+    let tier = reader.varint_u32()?;
+    let modifier_count = reader.varint()? as usize;
+    let mut modifiers = Vec::new();
+    for _ in 0..modifier_count {
+        let code = reader.byte()?;
+        let value = i32::from_le_bytes(reader.array()?);
+        modifiers.push(area_modifier_from_code(code, value)?);
+    }
+    let mut connectors = [None, None];
+    for connector in connectors.iter_mut() {
+        let code = reader.byte()?;
+        if code != 0 {
+            *connector = Some(Connector {
+                side: side_from_code(code - 1)?,
+                offset: f32::from_le_bytes(reader.array()?),
+            });
+        }
+    }
+    let [entry, exit] = connectors;
+    let side_gate_count = reader.varint()? as usize;
+    let mut side_gates = Vec::new();
+    for _ in 0..side_gate_count {
+        side_gates.push(Connector {
+            side: side_from_code(reader.byte()?)?,
+            offset: f32::from_le_bytes(reader.array()?),
+        });
+    }
+    let rerolls = Rerolls {
+        layout: reader.varint_u32()?,
+        landmarks: reader.varint_u32()?,
+        packs: reader.varint_u32()?,
+        mobs: reader.varint_u32()?,
+    };
+    // sizes that do not fit fail the check too
+    let tile_size = i32::try_from(reader.varint()?).unwrap_or(0);
+    let mob_size = i32::try_from(reader.varint()?).unwrap_or(0);
+    if tile_size <= 0 || mob_size <= 0 {
+        return Err(DecodeError::Invalid("tile and mob sizes must be positive"));
+    }
+
+    let width = reader.varint()? as usize;
+    let height = reader.varint()? as usize;
+    // the dimensions come from the bytes, check them before allocating anything for them
+    let tile_count = width
+        .checked_mul(height)
+        .filter(|tile_count| *tile_count <= MAX_TILES)
+        // the positions in pixels are i32
+        .filter(|_| {
+            [width, height]
+                .iter()
+                .all(|side| (*side as i64 + 1) * tile_size as i64 <= i32::MAX as i64)
+        })
+        .ok_or(DecodeError::Invalid("area is too large"))?;
+    // the gates are a tile inside the edges
+    let has_gates = entry.is_some() || exit.is_some() || !side_gates.is_empty();
+    if has_gates && (width < 2 || height < 2) {
+        return Err(DecodeError::Invalid("area is too small for its gates"));
+    }
+    let mut tiles = Vec::with_capacity(tile_count.min(content.len() - reader.position));
+    let mut walkable = WalkableBitmap::new(width, height);
+    let mut walkable_x = Vec::new();
+    let mut walkable_y = Vec::new();
+    while tiles.len() < tile_count {
+        let value = reader.byte()?;
+        let length = reader.varint()? as usize;
+        if length > tile_count - tiles.len() {
+            return Err(DecodeError::Invalid("tile runs are longer than the area"));
+        }
+        let tile_type = tile_from_code(value & 0x7f)?;
+        for _ in 0..length {
+            let index = tiles.len();
+            if value & 0x80 != 0 {
+                walkable.set(index / height, index % height, true);
+                walkable_x.push((index / height) as u32);
+                walkable_y.push((index % height) as u32);
+            }
+            tiles.push(tile_type);
+        }
+    }
+
+    let player_spawn_position = (
+        i32::from_le_bytes(reader.array()?),
+        i32::from_le_bytes(reader.array()?),
+    );
+
+    let polygon_count = reader.varint()? as usize;
+    let mut oob_polygons = Vec::new();
+    for _ in 0..polygon_count {
+        let inner_if_true = reader.byte()? & 1 != 0;
+        let parent = match reader.varint()? {
+            0 => None,
+            parent => Some(parent as usize - 1),
+        };
+        if parent.is_some_and(|parent| parent >= polygon_count) {
+            return Err(DecodeError::Invalid("polygon parent out of range"));
+        }
+        let depth = reader.varint()? as usize;
+        let point_count = reader.varint()? as usize;
+        let mut points = Vec::new();
+        for _ in 0..point_count {
+            points.push((
+                f32::from_le_bytes(reader.array()?),
+                f32::from_le_bytes(reader.array()?),
+            ));
+        }
+        oob_polygons.push(Shape {
+            points,
+            inner_if_true,
+            parent,
+            children: Vec::new(),
+            depth,
+        });
+    }
+    for index in 0..oob_polygons.len() {
+        if let Some(parent) = oob_polygons[index].parent {
+            oob_polygons[parent].children.push(index);
+        }
+    }
+
+    let pack_count = reader.varint()? as usize;
+    let mut packs = Vec::new();
+    for _ in 0..pack_count {
+        packs.push(Pack {
+            tile: read_tile(&mut reader, width, height)?,
+            difficulty: f32::from_le_bytes(reader.array()?),
+        });
+    }
+
+    let monster_id_count = reader.varint()? as usize;
+    let mut monster_ids = Vec::new();
+    for _ in 0..monster_id_count {
+        let length = reader.varint()? as usize;
+        monster_ids.push(
+            String::from_utf8(reader.take(length)?.to_vec())
+                .map_err(|_| DecodeError::Invalid("monster id is not utf8"))?,
+        );
+    }
+    let enemy_count = reader.varint()? as usize;
+    let mut enemies = Vec::new();
+    for _ in 0..enemy_count {
+        let point = (reader.varint_u32()?, reader.varint_u32()?);
+        let pack = reader.varint()? as usize;
+        if pack >= packs.len() {
+            return Err(DecodeError::Invalid("enemy pack out of range"));
+        }
+        let monster_id = monster_ids
+            .get(reader.varint()? as usize)
+            .ok_or(DecodeError::Invalid("monster id index out of range"))?
+            .clone();
+        let rarity = rarity_from_code(reader.byte()?)?;
+        let modifier_count = reader.varint()? as usize;
+        let mut modifiers = Vec::new();
+        for _ in 0..modifier_count {
+            modifiers.push(modifier_from_code(reader.byte()?)?);
+        }
+        enemies.push(Enemy {
+            point,
+            monster_id,
+            rarity,
+            modifiers,
+            pack,
+        });
+    }
+    let landmark_count = reader.varint()? as usize;
+    let mut landmarks = Vec::new();
+    for _ in 0..landmark_count {
+        let kind = landmark_from_code(reader.byte()?)?;
+        let tile = read_tile(&mut reader, width, height)?;
+        landmarks.push(Landmark {
+            kind,
+            tile,
+            point: tile_center((tile.0 as usize, tile.1 as usize), tile_size),
+        });
+    }
+    if reader.position != content.len() {
+        return Err(DecodeError::Invalid("unexpected data after the landmarks"));
+    }
+
+    let gates = connectors::gates(entry, exit, &side_gates, width, height, tile_size);
+    Ok(AreaGenerationOutput {
+        metadata: AreaMetadata {
+            map_name,
+            seed,
+            tier,
+            modifiers,
+            entry,
+            exit,
+            side_gates,
+            rerolls,
+        },
+        tile_size,
+        mob_size,
+        width: width as u32,
+        height: height as u32,
+        walkable_x,
+        walkable_y,
+        tiles,
+        walkable,
+        oob_polygons,
+        player_spawn_position,
+        packs,
+        enemies,
+        landmarks,
+        gates,
+        widened_tiles,
+        nearest_pack_distance: None,
+        navmesh: None,
+        colliders: Vec::new(),
+    })
+}
+
+// also reads the seed codes
+pub(crate) struct Reader<'a> {
+    pub(crate) bytes: &'a [u8],
+    pub(crate) position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, length: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(DecodeError::Truncated)?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::Invalid("varint is too long"))
+    }
+
+    // for the values that are u32 in memory, the encoder never writes bigger ones
+    pub(crate) fn varint_u32(&mut self) -> Result<u32, DecodeError> {
+        u32::try_from(self.varint()?).map_err(|_| DecodeError::Invalid("varint is too big"))
+    }
+}
+
+fn read_tile(reader: &mut Reader, width: usize, height: usize) -> Result<(u32, u32), DecodeError> {
+    let tile = (reader.varint_u32()?, reader.varint_u32()?);
+    if tile.0 as usize >= width || tile.1 as usize >= height {
+        return Err(DecodeError::Invalid("tile outside of the area"));
+    }
+    Ok(tile)
+}
+
+pub(crate) fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+// Codes are part of the format, never reuse a removed one
+fn tile_code(tile_type: TileType) -> u8 {
+    match tile_type {
+        TileType::Floor => 0,
+        TileType::Wall => 1,
+        TileType::Start => 2,
+        TileType::Boss => 3,
+        TileType::Event => 4,
+        TileType::Water => 5,
+        TileType::Forest => 6,
+        TileType::Angle => 7,
+        TileType::Exit => 8,
+        TileType::Waypoint => 9,
+        TileType::Checkpoint => 10,
+    }
+}
+
+fn tile_from_code(code: u8) -> Result<TileType, DecodeError> {
+    Ok(match code {
+        0 => TileType::Floor,
+        1 => TileType::Wall,
+        2 => TileType::Start,
+        3 => TileType::Boss,
+        4 => TileType::Event,
+        5 => TileType::Water,
+        6 => TileType::Forest,
+        7 => TileType::Angle,
+        8 => TileType::Exit,
+        9 => TileType::Waypoint,
+        10 => TileType::Checkpoint,
+        _ => return Err(DecodeError::Invalid("unknown tile type")),
+    })
+}
+
+fn rarity_code(rarity: Rarity) -> u8 {
+    match rarity {
+        Rarity::Normal => 0,
+        Rarity::Rare => 1,
+        Rarity::Unique => 2,
+    }
+}
+
+fn rarity_from_code(code: u8) -> Result<Rarity, DecodeError> {
+    Ok(match code {
+        0 => Rarity::Normal,
+        1 => Rarity::Rare,
+        2 => Rarity::Unique,
+        _ => return Err(DecodeError::Invalid("unknown rarity")),
+    })
+}
+
+fn modifier_code(modifier: MonsterModifier) -> u8 {
+    match modifier {
+        MonsterModifier::Fast => 0,
+        MonsterModifier::ExtraHealth => 1,
+        MonsterModifier::ExtraDamage => 2,
+        MonsterModifier::FireAura => 3,
+        MonsterModifier::FrostAura => 4,
+        MonsterModifier::Regenerating => 5,
+        MonsterModifier::Teleporting => 6,
+        MonsterModifier::Splitting => 7,
+    }
+}
+
+fn modifier_from_code(code: u8) -> Result<MonsterModifier, DecodeError> {
+    Ok(match code {
+        0 => MonsterModifier::Fast,
+        1 => MonsterModifier::ExtraHealth,
+        2 => MonsterModifier::ExtraDamage,
+        3 => MonsterModifier::FireAura,
+        4 => MonsterModifier::FrostAura,
+        5 => MonsterModifier::Regenerating,
+        6 => MonsterModifier::Teleporting,
+        7 => MonsterModifier::Splitting,
+        _ => return Err(DecodeError::Invalid("unknown monster modifier")),
+    })
+}
+
+pub(crate) fn area_modifier_code(modifier: AreaModifier) -> (u8, i32) {
+    match modifier {
+        AreaModifier::PackSize(percent) => (0, percent),
+        AreaModifier::AreaSize(percent) => (1, percent),
+        AreaModifier::Density(percent) => (2, percent),
+        AreaModifier::RareChance(percent) => (3, percent),
+        AreaModifier::UniqueChance(percent) => (4, percent),
+        AreaModifier::ExtraMonsterModifiers(count) => (5, count as i32),
+        AreaModifier::NoPacks => (6, 0),
+    }
+}
+
+pub(crate) fn area_modifier_from_code(code: u8, value: i32) -> Result<AreaModifier, DecodeError> {
+    Ok(match code {
+        0 => AreaModifier::PackSize(value),
+        1 => AreaModifier::AreaSize(value),
+        2 => AreaModifier::Density(value),
+        3 => AreaModifier::RareChance(value),
+        4 => AreaModifier::UniqueChance(value),
+        5 => AreaModifier::ExtraMonsterModifiers(value as u32),
+        6 => AreaModifier::NoPacks,
+        _ => return Err(DecodeError::Invalid("unknown area modifier")),
+    })
+}
+
+fn landmark_code(kind: LandmarkKind) -> u8 {
+    match kind {
+        LandmarkKind::Exit => 0,
+        LandmarkKind::Waypoint => 1,
+        LandmarkKind::Checkpoint => 2,
+    }
+}
+
+fn landmark_from_code(code: u8) -> Result<LandmarkKind, DecodeError> {
+    Ok(match code {
+        0 => LandmarkKind::Exit,
+        1 => LandmarkKind::Waypoint,
+        2 => LandmarkKind::Checkpoint,
+        _ => return Err(DecodeError::Invalid("unknown landmark kind")),
+    })
+}
+
+pub(crate) fn side_code(side: Side) -> u8 {
+    match side {
+        Side::North => 0,
+        Side::East => 1,
+        Side::South => 2,
+        Side::West => 3,
+    }
+}
+
+pub(crate) fn side_from_code(code: u8) -> Result<Side, DecodeError> {
+    Ok(match code {
+        0 => Side::North,
+        1 => Side::East,
+        2 => Side::South,
+        3 => Side::West,
+        _ => return Err(DecodeError::Invalid("unknown connector side")),
+    })
+}
+
+// CRC-32 (IEEE), bitwise, the areas are small enough to not need a table
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::{generate_area, GenerationConfig, GenerationRequest};
+
+    fn request(map_index: usize, seed: u64) -> GenerationRequest {
+        // cover the tiers, modifiers and connectors of the format too
+        let exit = Connector {
+            side: Side::East,
+            offset: 0.25,
+        };
+        GenerationRequest {
+            tier: (seed % 16) as u32,
+            modifiers: vec![
+                AreaModifier::PackSize(30),
+                AreaModifier::RareChance(-20),
+                AreaModifier::ExtraMonsterModifiers(1),
+            ],
+            entry: Some(exit.facing()),
+            exit: Some(exit),
+            side_gates: vec![Connector {
+                side: Side::North,
+                offset: 0.6,
+            }],
+            ..GenerationRequest::new(map_index, seed)
+        }
+    }
+
+    // the header and metadata of an empty area, up to the dimensions
+    fn header() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        // name, widened tiles, tier, modifiers, entry, exit, side gates, rerolls
+        bytes.extend_from_slice(&[0; 11]);
+        write_varint(&mut bytes, 60);
+        write_varint(&mut bytes, 20);
+        bytes
+    }
+
+    fn with_checksum(mut bytes: Vec<u8>) -> Vec<u8> {
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn decoding_gives_back_the_same_area() {
+        let config = GenerationConfig::headless();
+        for map_index in 0..5 {
+            for seed in [1, 2, 3, 0xdead_beef] {
+                let request = request(map_index, seed);
+                let area = generate_area(&request, &config);
+
+                let bytes = encode_area(&area);
+                let decoded = decode_area(&bytes).unwrap();
+                assert_eq!(
+                    encode_area(&decoded),
+                    bytes,
+                    "map {} seed {}",
+                    map_index,
+                    seed
+                );
+                assert_eq!(decoded.metadata, area.metadata);
+                assert_eq!(decoded.tile_size, area.tile_size);
+                assert_eq!(decoded.mob_size, area.mob_size);
+                assert_eq!(decoded.tiles, area.tiles);
+                assert_eq!(decoded.walkable_x, area.walkable_x);
+                assert_eq!(decoded.walkable_y, area.walkable_y);
+                assert_eq!(decoded.enemies.len(), area.enemies.len());
+                assert_eq!(decoded.gates.len(), area.gates.len());
+                for (decoded, gate) in decoded.gates.iter().zip(&area.gates) {
+                    assert_eq!(decoded.tiles, gate.tiles);
+                    assert_eq!(decoded.seam, gate.seam);
+                }
+                assert_eq!(decoded.oob_polygons.len(), area.oob_polygons.len());
+                for (decoded, shape) in decoded.oob_polygons.iter().zip(&area.oob_polygons) {
+                    // children order is not part of the format
+                    let mut children = shape.children.clone();
+                    children.sort();
+                    assert_eq!(decoded.points, shape.points);
+                    assert_eq!(decoded.children, children);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn crafted_dimensions_are_rejected() {
+        for (width, height) in [(u64::MAX / 2, 3), (1 << 32, 1 << 32), (1 << 13, 1 << 13)] {
+            let mut bytes = header();
+            write_varint(&mut bytes, width);
+            write_varint(&mut bytes, height);
+            assert_eq!(
+                decode_area(&with_checksum(bytes)).err(),
+                Some(DecodeError::Invalid("area is too large")),
+                "{} x {}",
+                width,
+                height
+            );
+        }
+        // an entry gate on an area a tile wide
+        let mut bytes = header();
+        bytes.splice(18..19, [1, 0, 0, 0, 0]);
+        write_varint(&mut bytes, 1);
+        write_varint(&mut bytes, 10);
+        assert_eq!(
+            decode_area(&with_checksum(bytes)).err(),
+            Some(DecodeError::Invalid("area is too small for its gates"))
+        );
+        // tiles so big the pixel positions do not fit
+        let mut bytes = header();
+        let tile_size = bytes.len() - 2;
+        let mut size = Vec::new();
+        write_varint(&mut size, 1 << 20);
+        bytes.splice(tile_size..tile_size + 1, size);
+        write_varint(&mut bytes, 4096);
+        write_varint(&mut bytes, 1);
+        assert_eq!(
+            decode_area(&with_checksum(bytes)).err(),
+            Some(DecodeError::Invalid("area is too large"))
+        );
+        // a sane size without the tiles it announces
+        let mut bytes = header();
+        write_varint(&mut bytes, 100);
+        write_varint(&mut bytes, 100);
+        assert_eq!(
+            decode_area(&with_checksum(bytes)).err(),
+            Some(DecodeError::Truncated)
+        );
+    }
+
+    // a 3 x 3 floor area without polygons, packs and the rest are added by fill
+    fn small_area(fill: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut bytes = header();
+        write_varint(&mut bytes, 3);
+        write_varint(&mut bytes, 3);
+        bytes.push(tile_code(TileType::Floor) | 0x80);
+        write_varint(&mut bytes, 9);
+        bytes.extend_from_slice(&[0; 8]);
+        // polygons
+        write_varint(&mut bytes, 0);
+        fill(&mut bytes);
+        with_checksum(bytes)
+    }
+
+    // one pack at the tile, no monster ids nor enemies, the landmarks are added by fill
+    fn with_pack(bytes: &mut Vec<u8>, tile: (u64, u64)) {
+        write_varint(bytes, 1);
+        write_varint(bytes, tile.0);
+        write_varint(bytes, tile.1);
+        bytes.extend_from_slice(&0.5f32.to_le_bytes());
+        write_varint(bytes, 0);
+        write_varint(bytes, 0);
+    }
+
+    #[test]
+    fn small_areas_decode() {
+        let area = decode_area(&small_area(|bytes| {
+            with_pack(bytes, (2, 2));
+            write_varint(bytes, 1);
+            bytes.push(landmark_code(LandmarkKind::Exit));
+            write_varint(bytes, 1);
+            write_varint(bytes, 0);
+        }))
+        .unwrap();
+        assert_eq!(area.walkable.count, 9);
+        assert_eq!(area.packs[0].tile, (2, 2));
+        assert_eq!(area.landmarks[0].tile, (1, 0));
+    }
+
+    #[test]
+    fn varints_bigger_than_their_value_are_rejected() {
+        let too_big = Some(DecodeError::Invalid("varint is too big"));
+        for tile in [(1 << 32, 0), (0, u32::MAX as u64 + 2)] {
+            let bytes = small_area(|bytes| {
+                with_pack(bytes, tile);
+                write_varint(bytes, 0);
+            });
+            assert_eq!(decode_area(&bytes).err(), too_big, "{:?}", tile);
+        }
+        // tier, right after the seed, the name and the widened tiles
+        let mut bytes = small_area(|bytes| bytes.extend_from_slice(&[0; 4]));
+        // checksum again below
+        bytes.truncate(bytes.len() - 4);
+        let mut tier = Vec::new();
+        write_varint(&mut tier, 1 << 40);
+        bytes.splice(16..17, tier);
+        assert_eq!(decode_area(&with_checksum(bytes)).err(), too_big);
+        // tile size
+        let mut bytes = header();
+        let tile_size = bytes.len() - 2;
+        let mut size = Vec::new();
+        write_varint(&mut size, (1 << 32) + 60);
+        bytes.splice(tile_size..tile_size + 1, size);
+        assert_eq!(
+            decode_area(&with_checksum(bytes)).err(),
+            Some(DecodeError::Invalid("tile and mob sizes must be positive"))
+        );
+        // a name longer than anything, the reader does not overflow
+        let mut bytes = header();
+        let mut length = Vec::new();
+        write_varint(&mut length, u64::MAX);
+        bytes.splice(14..15, length);
+        assert_eq!(
+            decode_area(&with_checksum(bytes)).err(),
+            Some(DecodeError::Truncated)
+        );
+    }
+
+    #[test]
+    fn tiles_outside_of_the_area_are_rejected() {
+        let outside = Some(DecodeError::Invalid("tile outside of the area"));
+        for tile in [(3, 0), (0, 3), (7, 7)] {
+            let pack = small_area(|bytes| {
+                with_pack(bytes, tile);
+                write_varint(bytes, 0);
+            });
+            assert_eq!(decode_area(&pack).err(), outside, "pack at {:?}", tile);
+            let landmark = small_area(|bytes| {
+                with_pack(bytes, (1, 1));
+                write_varint(bytes, 1);
+                bytes.push(landmark_code(LandmarkKind::Waypoint));
+                write_varint(bytes, tile.0);
+                write_varint(bytes, tile.1);
+            });
+            assert_eq!(
+                decode_area(&landmark).err(),
+                outside,
+                "landmark at {:?}",
+                tile
+            );
+        }
+    }
+
+    #[test]
+    fn other_versions_are_not_decoded() {
+        let mut bytes = header();
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            decode_area(&with_checksum(bytes)).err(),
+            Some(DecodeError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+    }
+}
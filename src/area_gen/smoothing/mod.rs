@@ -0,0 +1,323 @@
+// Morphological smoothing passes, run on the walkable area after the layout is generated
+use super::{add_tile, corridors, Grid};
+use crate::area_gen::maps::TileType;
+
+#[derive(Clone, Copy)]
+pub enum StructuringElement {
+    // (2r+1) x (2r+1) square
+    Square(i32),
+    // plus shape with arms of length r
+    Cross(i32),
+    // every offset within a distance of r
+    Disk(i32),
+}
+
+#[derive(Clone, Copy)]
+pub enum SmoothingStep {
+    // remove floor tiles that are not fully surrounded by floor
+    Erode(StructuringElement),
+    // turn oob tiles touching the floor into floor
+    Dilate(StructuringElement),
+    // erode then dilate, removes thin spikes and small floor bits
+    Open(StructuringElement),
+    // dilate then erode, fills small gaps and rounds concave corners
+    Close(StructuringElement),
+    // widen every passage narrower than the given number of tiles
+    MinCorridorWidth(usize),
+    // fill floor tiles that only touch each other by a corner
+    FixDiagonalPinches,
+}
+
+impl StructuringElement {
+    fn radius(&self) -> i32 {
+        match *self {
+            StructuringElement::Square(r)
+            | StructuringElement::Cross(r)
+            | StructuringElement::Disk(r) => r,
+        }
+    }
+
+    fn offsets(&self) -> Vec<(i32, i32)> {
+        let r = self.radius();
+        let mut offsets = Vec::new();
+        for dx in -r..=r {
+            for dy in -r..=r {
+                let keep = match self {
+                    StructuringElement::Square(_) => true,
+                    StructuringElement::Cross(_) => dx == 0 || dy == 0,
+                    StructuringElement::Disk(_) => dx * dx + dy * dy <= r * r,
+                };
+                if keep {
+                    offsets.push((dx, dy));
+                }
+            }
+        }
+        offsets
+    }
+}
+
+// Apply a smoothing step to the grid, returns the number of tiles that changed
+pub fn apply_step(grid: &mut Grid, oob_tiletype: TileType, step: &SmoothingStep) -> usize {
+    match step {
+        SmoothingStep::Erode(element) => {
+            let mask = walkable_mask(grid);
+            let eroded = erode(&mask, element);
+            apply_mask(grid, &mask, &eroded, oob_tiletype)
+        }
+        SmoothingStep::Dilate(element) => {
+            let mask = walkable_mask(grid);
+            let dilated = dilate(&mask, element);
+            apply_mask(grid, &mask, &dilated, oob_tiletype)
+        }
+        SmoothingStep::Open(element) => {
+            let mask = walkable_mask(grid);
+            let opened = dilate(&erode(&mask, element), element);
+            apply_mask(grid, &mask, &opened, oob_tiletype)
+        }
+        SmoothingStep::Close(element) => {
+            let mask = walkable_mask(grid);
+            let closed = erode(&dilate(&mask, element), element);
+            apply_mask(grid, &mask, &closed, oob_tiletype)
+        }
+        SmoothingStep::MinCorridorWidth(width) => {
+            corridors::enforce_min_corridor_width(grid, *width)
+        }
+        SmoothingStep::FixDiagonalPinches => fix_diagonal_pinches(grid),
+    }
+}
+
+// Fill one oob tile of every 2x2 block where floor tiles only touch by a corner
+pub fn fix_diagonal_pinches(grid: &mut Grid) -> usize {
+    let mut changed = 0;
+    loop {
+        let mut tiles_to_fill = Vec::new();
+        for x in 0..grid.len() - 1 {
+            for y in 0..grid[0].len() - 1 {
+                let top_left = grid[x][y].walkable;
+                let top_right = grid[x + 1][y].walkable;
+                let bottom_left = grid[x][y + 1].walkable;
+                let bottom_right = grid[x + 1][y + 1].walkable;
+                if top_left && bottom_right && !top_right && !bottom_left {
+                    tiles_to_fill.push((x + 1, y));
+                } else if top_right && bottom_left && !top_left && !bottom_right {
+                    tiles_to_fill.push((x, y));
+                }
+            }
+        }
+        if tiles_to_fill.is_empty() {
+            break;
+        }
+        for (x, y) in tiles_to_fill {
+            if !grid[x][y].walkable {
+                add_tile(grid, x, y, TileType::Floor, true, false);
+                changed += 1;
+            }
+        }
+    }
+    changed
+}
+
+// Turn into oob every floor tile that can not be reached from the anchor
+pub fn remove_unreachable_floor(
+    grid: &mut Grid,
+    oob_tiletype: TileType,
+    anchor: (i32, i32),
+) -> usize {
+    // nothing can be reached from an anchor outside of the floor, keep the grid as it is
+    if !is_set(&walkable_mask(grid), anchor.0, anchor.1) {
+        return 0;
+    }
+    let mut reached = vec![vec![false; grid[0].len()]; grid.len()];
+    let mut to_visit = vec![(anchor.0 as usize, anchor.1 as usize)];
+    reached[anchor.0 as usize][anchor.1 as usize] = true;
+    while let Some((x, y)) = to_visit.pop() {
+        let neighbours = [
+            Some((x + 1, y)),
+            x.checked_sub(1).map(|nx| (nx, y)),
+            Some((x, y + 1)),
+            y.checked_sub(1).map(|ny| (x, ny)),
+        ];
+        for (nx, ny) in neighbours.into_iter().flatten() {
+            if nx < grid.len() && ny < grid[0].len() && grid[nx][ny].walkable && !reached[nx][ny] {
+                reached[nx][ny] = true;
+                to_visit.push((nx, ny));
+            }
+        }
+    }
+    let mut changed = 0;
+    for x in 0..grid.len() {
+        for y in 0..grid[0].len() {
+            if grid[x][y].walkable && !reached[x][y] {
+                add_tile(grid, x, y, oob_tiletype, false, false);
+                changed += 1;
+            }
+        }
+    }
+    changed
+}
+
+pub(crate) fn walkable_mask(grid: &Grid) -> Vec<Vec<bool>> {
+    grid.iter()
+        .map(|row| row.iter().map(|tile| tile.walkable).collect())
+        .collect()
+}
+
+// Bounding box of the walkable tiles, grown by margin and kept inside the grid
+pub(crate) fn walkable_bounds(mask: &[Vec<bool>], margin: i32) -> Option<((i32, i32), (i32, i32))> {
+    let mut min = (i32::MAX, i32::MAX);
+    let mut max = (i32::MIN, i32::MIN);
+    for (x, column) in mask.iter().enumerate() {
+        for (y, set) in column.iter().enumerate() {
+            if *set {
+                min = (min.0.min(x as i32), min.1.min(y as i32));
+                max = (max.0.max(x as i32), max.1.max(y as i32));
+            }
+        }
+    }
+    if min.0 > max.0 {
+        return None;
+    }
+    // keep one tile of border so neighbours can always be indexed
+    Some((
+        ((min.0 - margin).max(1), (min.1 - margin).max(1)),
+        (
+            (max.0 + margin).min(mask.len() as i32 - 2),
+            (max.1 + margin).min(mask[0].len() as i32 - 2),
+        ),
+    ))
+}
+
+fn erode(mask: &[Vec<bool>], element: &StructuringElement) -> Vec<Vec<bool>> {
+    let offsets = element.offsets();
+    let mut result = mask.to_vec();
+    if let Some((min, max)) = walkable_bounds(mask, 0) {
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                if mask[x as usize][y as usize] {
+                    result[x as usize][y as usize] = offsets
+                        .iter()
+                        .all(|offset| is_set(mask, x + offset.0, y + offset.1));
+                }
+            }
+        }
+    }
+    result
+}
+
+fn dilate(mask: &[Vec<bool>], element: &StructuringElement) -> Vec<Vec<bool>> {
+    let offsets = element.offsets();
+    let mut result = mask.to_vec();
+    if let Some((min, max)) = walkable_bounds(mask, element.radius()) {
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                if !mask[x as usize][y as usize] {
+                    result[x as usize][y as usize] = offsets
+                        .iter()
+                        .any(|offset| is_set(mask, x + offset.0, y + offset.1));
+                }
+            }
+        }
+    }
+    result
+}
+
+fn is_set(mask: &[Vec<bool>], x: i32, y: i32) -> bool {
+    x >= 0
+        && y >= 0
+        && (x as usize) < mask.len()
+        && (y as usize) < mask[0].len()
+        && mask[x as usize][y as usize]
+}
+
+// Write the difference between two masks back to the grid
+fn apply_mask(
+    grid: &mut Grid,
+    before: &[Vec<bool>],
+    after: &[Vec<bool>],
+    oob_tiletype: TileType,
+) -> usize {
+    let mut changed = 0;
+    for x in 0..grid.len() {
+        for y in 0..grid[0].len() {
+            if before[x][y] != after[x][y] {
+                if after[x][y] {
+                    add_tile(grid, x, y, TileType::Floor, true, false);
+                } else {
+                    add_tile(grid, x, y, oob_tiletype, false, false);
+                }
+                changed += 1;
+            }
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::test_helpers::{grid_from, rows_of};
+
+    #[test]
+    fn open_removes_spikes() {
+        let mut grid = grid_from(&[
+            "#########",
+            "#.....#.#",
+            "#.....#.#",
+            "#.......#",
+            "#.....###",
+            "#.....###",
+            "#########",
+        ]);
+        let changed = apply_step(
+            &mut grid,
+            TileType::Wall,
+            &SmoothingStep::Open(StructuringElement::Square(1)),
+        );
+        assert_eq!(changed, 4);
+        assert!(!grid[7][1].walkable && !grid[7][3].walkable);
+        assert!(grid[3][3].walkable);
+    }
+
+    #[test]
+    fn close_fills_small_holes() {
+        let mut grid = grid_from(&[
+            "#########",
+            "#.......#",
+            "#...#...#",
+            "#...#...#",
+            "#.......#",
+            "#########",
+        ]);
+        apply_step(
+            &mut grid,
+            TileType::Wall,
+            &SmoothingStep::Close(StructuringElement::Cross(1)),
+        );
+        assert!(grid[4][2].walkable && grid[4][3].walkable);
+    }
+
+    #[test]
+    fn diagonal_pinches_are_filled() {
+        let mut grid = grid_from(&["######", "#..###", "#..###", "###..#", "###..#", "######"]);
+        assert!(fix_diagonal_pinches(&mut grid) > 0);
+        assert_eq!(fix_diagonal_pinches(&mut grid), 0);
+    }
+
+    #[test]
+    fn unreachable_floor_on_the_grid_edge() {
+        // floor touching the edges must not be read out of bounds
+        let mut grid = grid_from(&["...#.", "...#.", "...##", "#####"]);
+        let changed = remove_unreachable_floor(&mut grid, TileType::Wall, (0, 0));
+        assert_eq!(changed, 2);
+        assert_eq!(rows_of(&grid), ["...##", "...##", "...##", "#####"]);
+        // an anchor outside of the floor changes nothing
+        assert_eq!(
+            remove_unreachable_floor(&mut grid, TileType::Wall, (4, 3)),
+            0
+        );
+        assert_eq!(
+            remove_unreachable_floor(&mut grid, TileType::Wall, (-1, 9)),
+            0
+        );
+    }
+}
@@ -0,0 +1,221 @@
+// Map tiers and area modifiers, applied to the map before the generation
+use super::maps::Map;
+
+// Percentages stack additively with the other modifiers of the same kind and the tier bonus
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AreaModifier {
+    // percent increased pack size
+    PackSize(i32),
+    // percent increased walkable area
+    AreaSize(i32),
+    // percent increased pack density
+    Density(i32),
+    // percent increased chance of rare pack leaders
+    RareChance(i32),
+    // percent increased chance of unique pack leaders
+    UniqueChance(i32),
+    // more modifiers on the rare and unique pack leaders
+    ExtraMonsterModifiers(u32),
+    // no packs at all whatever the tier and the other modifiers, e.g. towns
+    NoPacks,
+}
+
+// Bonus of each tier above the first one, in percent
+const TIER_PACK_SIZE: i32 = 3;
+const TIER_AREA_SIZE: i32 = 2;
+const TIER_DENSITY: i32 = 4;
+const TIER_RARE_CHANCE: i32 = 15;
+// one more modifier on rare and unique leaders every few tiers
+const TIERS_PER_EXTRA_MONSTER_MODIFIER: u32 = 5;
+// tiers above get the bonus of this one
+pub const MAX_TIER: u32 = 100;
+// the generation grid fits twice the base area, tested with every map at MAX_TIER
+const MAX_AREA_SIZE: f64 = 2.0;
+// packs can not get more than that many times bigger
+const MAX_PACK_SIZE: f64 = 10.0;
+
+// Totals of a tier and its modifiers
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TierScaling {
+    pub pack_size: f64,
+    pub area_size: f64,
+    pub density: f64,
+    pub rare_chance: f64,
+    pub unique_chance: f64,
+    pub extra_monster_modifiers: usize,
+}
+
+impl TierScaling {
+    // tier 0 and 1 have no bonus
+    pub fn new(tier: u32, modifiers: &[AreaModifier]) -> TierScaling {
+        // summed as i64, the percentages of the modifiers can be anything
+        let bonus_tiers = tier.clamp(1, MAX_TIER) as i64 - 1;
+        let mut pack_size = TIER_PACK_SIZE as i64 * bonus_tiers;
+        let mut area_size = TIER_AREA_SIZE as i64 * bonus_tiers;
+        let mut density = TIER_DENSITY as i64 * bonus_tiers;
+        let mut rare_chance = TIER_RARE_CHANCE as i64 * bonus_tiers;
+        let mut unique_chance = 0;
+        let mut extra_monster_modifiers = bonus_tiers as u32 / TIERS_PER_EXTRA_MONSTER_MODIFIER;
+        let mut no_packs = false;
+        for modifier in modifiers {
+            match *modifier {
+                AreaModifier::PackSize(percent) => pack_size += percent as i64,
+                AreaModifier::AreaSize(percent) => area_size += percent as i64,
+                AreaModifier::Density(percent) => density += percent as i64,
+                AreaModifier::RareChance(percent) => rare_chance += percent as i64,
+                AreaModifier::UniqueChance(percent) => unique_chance += percent as i64,
+                AreaModifier::ExtraMonsterModifiers(count) => {
+                    extra_monster_modifiers = extra_monster_modifiers.saturating_add(count)
+                }
+                AreaModifier::NoPacks => no_packs = true,
+            }
+        }
+        // reductions can not go below nothing
+        let multiplier = |percent: i64| (1.0 + percent as f64 / 100.0).max(0.0);
+        TierScaling {
+            pack_size: multiplier(pack_size).min(MAX_PACK_SIZE),
+            area_size: multiplier(area_size).clamp(0.25, MAX_AREA_SIZE),
+            density: if no_packs { 0.0 } else { multiplier(density) },
+            rare_chance: multiplier(rare_chance),
+            unique_chance: multiplier(unique_chance),
+            extra_monster_modifiers: extra_monster_modifiers as usize,
+        }
+    }
+
+    pub fn apply(&self, map: &mut Map) {
+        // rectangles grow on both axis
+        let side = self.area_size.sqrt();
+        for biome in map.biomes.iter_mut() {
+            biome.generation_area_size = (
+                (biome.generation_area_size.0 as f64 * side).round() as i32,
+                (biome.generation_area_size.1 as f64 * side).round() as i32,
+            );
+        }
+        map.density *= self.density;
+
+        let monsters = &mut map.monsters;
+        for pack in monsters.packs.iter_mut() {
+            pack.size = (
+                (pack.size.0 as f64 * self.pack_size).round() as usize,
+                (pack.size.1 as f64 * self.pack_size).round() as usize,
+            );
+        }
+        monsters.rare.chance = (monsters.rare.chance * self.rare_chance).min(1.0);
+        monsters.unique.chance = (monsters.unique.chance * self.unique_chance).min(1.0);
+        for roll in [&mut monsters.rare, &mut monsters.unique] {
+            roll.modifier_count.0 += self.extra_monster_modifiers;
+            roll.modifier_count.1 += self.extra_monster_modifiers;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::area_gen::maps::define_floor_patterns;
+    use crate::area_gen::mobs::Rarity;
+    use crate::area_gen::{generate_area, GenerationConfig, GenerationRequest};
+
+    fn measure(
+        map_index: usize,
+        seed: u64,
+        tier: u32,
+        modifiers: Vec<AreaModifier>,
+    ) -> (usize, usize, usize) {
+        let area = generate_area(
+            &GenerationRequest {
+                tier,
+                modifiers,
+                ..GenerationRequest::new(map_index, seed)
+            },
+            &GenerationConfig::headless(),
+        );
+        let rares = area
+            .enemies
+            .iter()
+            .filter(|enemy| enemy.rarity != Rarity::Normal)
+            .count();
+        (area.walkable_x.len(), area.packs.len(), rares)
+    }
+
+    #[test]
+    fn every_map_fits_at_the_maximum_scaling() {
+        let modifiers = vec![
+            AreaModifier::AreaSize(1000),
+            AreaModifier::Density(1000),
+            AreaModifier::PackSize(1000),
+        ];
+        for map_index in 0..define_floor_patterns().len() {
+            for seed in [1, 2] {
+                measure(map_index, seed, MAX_TIER, modifiers.clone());
+                measure(map_index, seed, u32::MAX, Vec::new());
+            }
+        }
+    }
+
+    #[test]
+    fn high_tiers_are_bigger_and_harder() {
+        for map_index in 0..define_floor_patterns().len() {
+            let mut base = (0, 0, 0);
+            let mut high = (0, 0, 0);
+            for seed in [1, 2, 3] {
+                let (area, packs, rares) = measure(map_index, seed, 1, Vec::new());
+                base = (base.0 + area, base.1 + packs, base.2 + rares);
+                let (area, packs, rares) = measure(map_index, seed, 40, Vec::new());
+                high = (high.0 + area, high.1 + packs, high.2 + rares);
+            }
+            assert!(
+                high.0 > base.0,
+                "map {} area {:?} {:?}",
+                map_index,
+                base,
+                high
+            );
+            assert!(
+                high.1 > base.1,
+                "map {} packs {:?} {:?}",
+                map_index,
+                base,
+                high
+            );
+            assert!(
+                high.2 > base.2,
+                "map {} rares {:?} {:?}",
+                map_index,
+                base,
+                high
+            );
+        }
+    }
+
+    #[test]
+    fn percentages_of_a_kind_stack_additively() {
+        let stacked = TierScaling::new(
+            1,
+            &[
+                AreaModifier::PackSize(30),
+                AreaModifier::PackSize(20),
+                AreaModifier::RareChance(-50),
+                AreaModifier::RareChance(-70),
+            ],
+        );
+        assert_eq!(stacked.pack_size, 1.5);
+        // and never go below nothing
+        assert_eq!(stacked.rare_chance, 0.0);
+        // tier 11 is ten tiers of bonus
+        let tier = TierScaling::new(11, &[AreaModifier::Density(-20)]);
+        assert_eq!(tier.density, 1.0 + (10 * TIER_DENSITY - 20) as f64 / 100.0);
+        assert_eq!(TierScaling::new(0, &[]), TierScaling::new(1, &[]));
+    }
+
+    #[test]
+    fn no_packs_overrides_the_density() {
+        let scaling = TierScaling::new(
+            MAX_TIER,
+            &[AreaModifier::Density(500), AreaModifier::NoPacks],
+        );
+        assert_eq!(scaling.density, 0.0);
+        let (_, packs, _) = measure(0, 1, MAX_TIER, vec![AreaModifier::NoPacks]);
+        assert_eq!(packs, 0);
+    }
+}
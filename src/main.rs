@@ -1,15 +1,78 @@
-use area_gen::generate_area;
+use map_gen::area_gen::acts::{generate_act, ActConfig};
+use map_gen::area_gen::challenges::{challenge_request, ChallengeRules, Date, Period};
+use map_gen::area_gen::dungeons::{generate_dungeon, DungeonRequest};
+use map_gen::area_gen::maps::define_floor_patterns;
+use map_gen::area_gen::search::{search_seeds, Constraint, SearchRequest};
+use map_gen::area_gen::seed_codes::{decode_seed_code, encode_seed_code};
+use map_gen::area_gen::{generate_area, GenerationConfig, GenerationRequest};
+use std::env;
 use std::thread;
 
-mod area_gen;
-
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    // `code <seed code>`: generate the area of a shared seed code
+    if args.get(1).map(String::as_str) == Some("code") {
+        let code = args.get(2).map(String::as_str).unwrap_or_default();
+        match decode_seed_code(code) {
+            Ok(request) => {
+                generate_area(&request, &GenerationConfig::default());
+            }
+            Err(error) => println!("Invalid seed code {} : {:?}", code, error),
+        }
+        return;
+    }
+    // `daily [date]` or `weekly [date]`: challenge area of a YYYY-MM-DD date, today by default
+    if let Some(period @ ("daily" | "weekly")) = args.get(1).map(String::as_str) {
+        let date = args
+            .get(2)
+            .and_then(|date| Date::parse(date))
+            .unwrap_or_else(Date::today);
+        let rules = ChallengeRules {
+            challenge_id: String::from(period),
+            period: if period == "daily" {
+                Period::Daily
+            } else {
+                Period::Weekly
+            },
+            ..ChallengeRules::default()
+        };
+        generate_area(
+            &challenge_request(date, &rules),
+            &GenerationConfig::default(),
+        );
+        return;
+    }
+    // `search <map name> <count> [constraint]...`: seeds of a map matching all the constraints,
+    // e.g. `search Island 500 holes>=3`
+    if args.get(1).map(String::as_str) == Some("search") {
+        search(&args[2..]);
+        return;
+    }
+    // `act <seed>`: generate an act and every area of it
+    if args.get(1).map(String::as_str) == Some("act") {
+        let seed = args
+            .get(2)
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(rand::random);
+        act(seed);
+        return;
+    }
+    // `dungeon <seed>`: generate a three floor dungeon
+    if args.get(1).map(String::as_str) == Some("dungeon") {
+        let seed = args
+            .get(2)
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(rand::random);
+        dungeon(seed);
+        return;
+    }
+
     // roll each maps in a sperate thread
     // for _ in 0..100 {
     let mut handlers = Vec::new();
     for i in 0..5 {
         handlers.push(thread::spawn(move || {
-            generate_area(i);
+            generate_area(&GenerationRequest::random(i), &GenerationConfig::default());
         }));
     }
     for handler in handlers {
@@ -17,3 +80,101 @@ fn main() {
     }
     // }
 }
+
+fn act(seed: u64) {
+    let act = match generate_act(seed, &ActConfig::default()) {
+        Ok(act) => act,
+        Err(error) => {
+            println!("Invalid act config : {:?}", error);
+            return;
+        }
+    };
+    println!("Act {} : {} areas", seed, act.nodes.len());
+    for edge in &act.edges {
+        println!(
+            "    {} -> {} through the {:?} side",
+            edge.from, edge.to, edge.connector.side
+        );
+    }
+    // nodes do not depend on each other, but the nodes of a map would all save their
+    // picture to the same file
+    let config = GenerationConfig {
+        render_image: false,
+        ..GenerationConfig::default()
+    };
+    let mut handlers = Vec::new();
+    for index in 0..act.nodes.len() {
+        let request = act.nodes[index].request();
+        let config = config.clone();
+        handlers.push(thread::spawn(move || {
+            generate_area(&request, &config);
+        }));
+    }
+    for handler in handlers {
+        handler.join().unwrap();
+    }
+}
+
+fn dungeon(seed: u64) {
+    let request = DungeonRequest {
+        seed,
+        // two quarry cellars above a ledge
+        floors: vec![4, 4, 1],
+        tier: 1,
+        modifiers: Vec::new(),
+    };
+    let dungeon = generate_dungeon(&request, &GenerationConfig::default());
+    for (level, floor) in dungeon.floors.iter().enumerate() {
+        println!(
+            "Floor {} : {} at {:?}, stairs up {:?}, stairs down {:?}",
+            level, floor.area.metadata.map_name, floor.offset, floor.stairs_up, floor.stairs_down
+        );
+    }
+}
+
+fn search(args: &[String]) {
+    let Some(map_index) = args.first().and_then(|name| {
+        define_floor_patterns()
+            .iter()
+            .position(|map| &map.name == name)
+    }) else {
+        println!("Unknown map, usage: search <map name> <count> [constraint]...");
+        return;
+    };
+    let count = args
+        .get(1)
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(100);
+    let mut constraints = Vec::new();
+    for text in args.iter().skip(2) {
+        match Constraint::parse(text) {
+            Some(constraint) => constraints.push(constraint),
+            None => {
+                println!("Invalid constraint {}, e.g. critical_path>400", text);
+                return;
+            }
+        }
+    }
+    let request = SearchRequest {
+        map_index,
+        tier: 1,
+        modifiers: Vec::new(),
+        entry: None,
+        exit: None,
+        first_seed: rand::random(),
+        count,
+        threads: thread::available_parallelism().map_or(4, |threads| threads.get()),
+        max_matches: None,
+        constraints,
+    };
+    let matches = search_seeds(&request);
+    println!("{} of {} seeds match", matches.len(), count);
+    for found in matches {
+        println!(
+            "{} : {}\n    {:?}",
+            found.request.seed,
+            encode_seed_code(&found.request),
+            found.metrics
+        );
+    }
+}